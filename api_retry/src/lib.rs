@@ -0,0 +1,253 @@
+use log::warn;
+use rand::Rng;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use wrapi::WrapiError;
+
+/// What a failed call says about trying it again
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+  /// Google turned the request away without acting on it, so it is safe to send again as is
+  Throttled,
+  /// The server failed part way through, so the request may or may not have taken effect
+  Transient,
+  /// Sending it again won't help
+  Permanent,
+}
+
+/// An error that can tell a RetryPolicy whether the call is worth another try
+pub trait Retryable {
+  fn failure(&self) -> Failure;
+
+  /// How long the server asked us to wait before trying again
+  fn retry_after(&self) -> Option<Duration> {
+    None
+  }
+}
+
+/// When and how often to try a call again after it fails
+///
+/// Waits between tries grow exponentially from base_delay, with full jitter so a batch of clients
+/// that failed together don't all come back at once. A Retry-After from the server replaces the
+/// computed wait.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+  /// Tries in total, counting the first. 1 turns retries off
+  pub max_attempts: u32,
+  /// The longest wait before the first retry. It doubles with each retry after that
+  pub base_delay: Duration,
+  /// The cap on any wait. A Retry-After longer than this returns the error instead of blocking
+  pub max_delay: Duration,
+  /// Also retry server errors on calls that aren't idempotent, like creating a file, at the risk
+  /// of doing it twice. Throttled calls are always retried since Google didn't act on them
+  pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> RetryPolicy {
+    RetryPolicy {
+      max_attempts: 5,
+      base_delay: Duration::from_millis(500),
+      max_delay: Duration::from_secs(32),
+      retry_non_idempotent: false,
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Make every call exactly once
+  pub fn none() -> RetryPolicy {
+    RetryPolicy {
+      max_attempts: 1,
+      ..Default::default()
+    }
+  }
+
+  /// How long to wait before retry number attempt, counting from 1
+  pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    if let Some(wait) = retry_after {
+      return wait;
+    }
+    let backoff = self
+      .base_delay
+      .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+      .unwrap_or(self.max_delay);
+    let cap = std::cmp::min(backoff, self.max_delay).as_millis() as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(0, cap + 1))
+  }
+
+  /// Run call until it works, it fails in a way retrying won't fix, or the attempts run out
+  ///
+  /// name is only used for logging. A call that isn't idempotent is only retried after a server
+  /// error when retry_non_idempotent is set.
+  pub fn run<T, E: Retryable + Debug>(
+    &self,
+    name: &str,
+    idempotent: bool,
+    mut call: impl FnMut() -> Result<T, E>,
+  ) -> Result<T, E> {
+    let mut attempt = 1;
+    loop {
+      let err = match call() {
+        Ok(result) => return Ok(result),
+        Err(err) => err,
+      };
+      let retry = match err.failure() {
+        Failure::Throttled => true,
+        Failure::Transient => idempotent || self.retry_non_idempotent,
+        Failure::Permanent => false,
+      };
+      let retry_after = err.retry_after();
+      if !retry
+        || attempt >= self.max_attempts
+        || matches!(retry_after, Some(wait) if wait > self.max_delay)
+      {
+        return Err(err);
+      }
+
+      let wait = self.delay(attempt, retry_after);
+      warn!(
+        "'{}' failed on attempt {} of {}, trying again in {:?}: {:?}",
+        name, attempt, self.max_attempts, wait, err
+      );
+      std::thread::sleep(wait);
+      attempt += 1;
+    }
+  }
+}
+
+/// The header line check_response puts in front of an error body
+const RETRY_AFTER: &str = "Retry-After:";
+
+/// Errors without a Google error body are either an HTTP status with some other body, like a
+/// proxy's error page, or the connection failing before any answer came back. A JSON error means a
+/// successful answer couldn't be read, which won't change on another try.
+impl Retryable for WrapiError {
+  fn failure(&self) -> Failure {
+    if let Some(error) = error_text(self).and_then(google_error) {
+      return match (error.code, error.reason.as_str()) {
+        (429, _) | (_, "rateLimitExceeded") | (_, "userRateLimitExceeded") => Failure::Throttled,
+        (500..=599, _) | (_, "backendError") | (_, "internalError") => Failure::Transient,
+        _ => Failure::Permanent,
+      };
+    }
+    let text = match self {
+      WrapiError::General(text) => text,
+      _ => return Failure::Permanent,
+    };
+    match http_status(text) {
+      Some(429) => Failure::Throttled,
+      Some(500..=599) | None => Failure::Transient,
+      Some(_) => Failure::Permanent,
+    }
+  }
+
+  /// Only the number of seconds form is understood, which is the one Google sends
+  fn retry_after(&self) -> Option<Duration> {
+    error_text(self)?
+      .lines()
+      .find_map(|line| {
+        match line.len() >= RETRY_AFTER.len()
+          && line[..RETRY_AFTER.len()].eq_ignore_ascii_case(RETRY_AFTER)
+        {
+          true => line[RETRY_AFTER.len()..].trim().parse::<u64>().ok(),
+          false => None,
+        }
+      })
+      .map(Duration::from_secs)
+  }
+}
+
+/// Fail with the body of a response that holds a Google error instead of a result
+///
+/// Call this first thing in WrapiResult::parse. The error keeps the Retry-After header, when
+/// there is one, so a RetryPolicy can wait as long as it was asked to.
+pub fn check_response(headers: &[(String, String)], body: &[u8]) -> Result<(), WrapiError> {
+  let text = match std::str::from_utf8(body) {
+    Ok(text) => text,
+    Err(_) => return Ok(()),
+  };
+  if google_error(text).is_none() {
+    return Ok(());
+  }
+  let retry_after = headers
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+    .map(|(_, value)| value);
+  Err(WrapiError::General(match retry_after {
+    Some(value) => format!("{} {}\n{}", RETRY_AFTER, value, text),
+    None => text.to_string(),
+  }))
+}
+
+/// The status code of an error made by http_error, skipping any Retry-After line before it
+fn http_status(text: &str) -> Option<u16> {
+  text
+    .lines()
+    .find(|line| !line.starts_with(RETRY_AFTER))?
+    .strip_prefix(HTTP_PREFIX)?
+    .split(':')
+    .next()?
+    .parse()
+    .ok()
+}
+
+/// The error for a response with a failing HTTP status whose body isn't a Google error
+pub fn http_error(status: u16, body: &[u8]) -> WrapiError {
+  WrapiError::General(format!(
+    "{}{}: {}",
+    HTTP_PREFIX,
+    status,
+    String::from_utf8_lossy(body)
+  ))
+}
+
+/// What http_error puts in front of the status code
+const HTTP_PREFIX: &str = "HTTP ";
+
+fn error_text(err: &WrapiError) -> Option<&str> {
+  if let WrapiError::General(text) = err {
+    Some(text)
+  } else if let WrapiError::Json(text) = err {
+    Some(text)
+  } else {
+    None
+  }
+}
+
+/// The error a Google API sent back in place of a result
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoogleError {
+  /// The HTTP status code
+  pub code: u16,
+  /// The reason of the first error listed, or the status when there is none, like "notFound"
+  pub reason: String,
+  pub message: String,
+}
+
+/// Pull a Google error body out of text, wherever it is in there
+pub fn google_error(text: &str) -> Option<GoogleError> {
+  let start = text.find('{')?;
+  let end = text.rfind('}')?;
+  if end < start {
+    return None;
+  }
+  let body: serde_json::Value = serde_json::from_str(&text[start..=end]).ok()?;
+  let error = body.get("error")?;
+  let code = error.get("code")?.as_u64()? as u16;
+  let reason = error
+    .pointer("/errors/0/reason")
+    .or_else(|| error.get("status"))
+    .and_then(|reason| reason.as_str())
+    .unwrap_or_default();
+  let message = error
+    .get("message")
+    .and_then(|message| message.as_str())
+    .unwrap_or_default();
+  Some(GoogleError {
+    code,
+    reason: reason.to_string(),
+    message: message.to_string(),
+  })
+}
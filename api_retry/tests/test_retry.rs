@@ -0,0 +1,165 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use api_retry::{
+  check_response, google_error as parse_error, http_error, Failure, GoogleError, RetryPolicy,
+  Retryable,
+};
+use wrapi::WrapiError;
+
+#[derive(Debug)]
+struct TestError(Failure);
+
+impl Retryable for TestError {
+  fn failure(&self) -> Failure {
+    self.0
+  }
+}
+
+fn quick(max_attempts: u32) -> RetryPolicy {
+  RetryPolicy {
+    max_attempts,
+    base_delay: Duration::from_millis(0),
+    ..Default::default()
+  }
+}
+
+fn google_error(code: u16, reason: &str) -> String {
+  format!(
+    r#"{{"error": {{"code": {}, "message": "Nope", "errors": [{{"reason": "{}"}}]}}}}"#,
+    code, reason
+  )
+}
+
+#[test]
+fn test_retry_attempts() {
+  let calls = Cell::new(0);
+  let result = quick(5).run("flaky", true, || {
+    calls.set(calls.get() + 1);
+    match calls.get() {
+      1 | 2 => Err(TestError(Failure::Transient)),
+      _ => Ok(calls.get()),
+    }
+  });
+  assert_eq!(result.unwrap(), 3);
+
+  calls.set(0);
+  let result: Result<(), _> = quick(3).run("down", true, || {
+    calls.set(calls.get() + 1);
+    Err(TestError(Failure::Throttled))
+  });
+  assert!(result.is_err());
+  assert_eq!(calls.get(), 3);
+
+  calls.set(0);
+  let result: Result<(), _> = quick(3).run("broken", true, || {
+    calls.set(calls.get() + 1);
+    Err(TestError(Failure::Permanent))
+  });
+  assert!(result.is_err());
+  assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn test_retry_idempotency() {
+  // A server error on a create may have created something, so it's left alone
+  let calls = Cell::new(0);
+  let result: Result<(), _> = quick(3).run("create", false, || {
+    calls.set(calls.get() + 1);
+    Err(TestError(Failure::Transient))
+  });
+  assert!(result.is_err());
+  assert_eq!(calls.get(), 1);
+
+  // Throttled calls were never acted on
+  calls.set(0);
+  let result: Result<(), _> = quick(3).run("create", false, || {
+    calls.set(calls.get() + 1);
+    Err(TestError(Failure::Throttled))
+  });
+  assert!(result.is_err());
+  assert_eq!(calls.get(), 3);
+
+  calls.set(0);
+  let policy = RetryPolicy {
+    retry_non_idempotent: true,
+    ..quick(3)
+  };
+  let result: Result<(), _> = policy.run("create", false, || {
+    calls.set(calls.get() + 1);
+    Err(TestError(Failure::Transient))
+  });
+  assert!(result.is_err());
+  assert_eq!(calls.get(), 3);
+}
+
+#[test]
+fn test_retry_delay() {
+  let policy = RetryPolicy::default();
+  for attempt in 1..20 {
+    let delay = policy.delay(attempt, None);
+    assert!(delay <= policy.max_delay);
+    if attempt <= 3 {
+      assert!(delay <= Duration::from_millis(500 * 2u64.pow(attempt - 1)));
+    }
+  }
+  assert_eq!(
+    policy.delay(1, Some(Duration::from_secs(7))),
+    Duration::from_secs(7)
+  );
+  assert_eq!(RetryPolicy::none().max_attempts, 1);
+}
+
+#[test]
+fn test_wrapi_errors() {
+  let failure = |text: String| WrapiError::General(text).failure();
+  assert_eq!(
+    failure(google_error(429, "rateLimitExceeded")),
+    Failure::Throttled
+  );
+  assert_eq!(
+    failure(google_error(403, "userRateLimitExceeded")),
+    Failure::Throttled
+  );
+  assert_eq!(
+    failure(google_error(503, "backendError")),
+    Failure::Transient
+  );
+  assert_eq!(failure(google_error(404, "notFound")), Failure::Permanent);
+  assert_eq!(
+    failure(google_error(403, "dailyLimitExceeded")),
+    Failure::Permanent
+  );
+  // Without a Google error body, only the HTTP status says anything about trying again
+  assert_eq!(failure("connection reset".to_string()), Failure::Transient);
+  let html = b"<html>Bad Gateway</html>";
+  assert_eq!(http_error(502, html).failure(), Failure::Transient);
+  assert_eq!(http_error(429, html).failure(), Failure::Throttled);
+  assert_eq!(http_error(404, html).failure(), Failure::Permanent);
+  assert_eq!(
+    WrapiError::Json("expected value at line 1 column 1".to_string()).failure(),
+    Failure::Permanent
+  );
+
+  let headers = vec![("retry-after".to_string(), "12".to_string())];
+  let body = google_error(429, "rateLimitExceeded");
+  let err = check_response(&headers, body.as_bytes()).unwrap_err();
+  assert_eq!(err.failure(), Failure::Throttled);
+  assert_eq!(err.retry_after(), Some(Duration::from_secs(12)));
+
+  assert!(check_response(&[], br#"{"files": []}"#).is_ok());
+  let err = check_response(&[], body.as_bytes()).unwrap_err();
+  assert_eq!(err.retry_after(), None);
+
+  assert_eq!(
+    parse_error(&format!("HTTP 404: {}", google_error(404, "notFound"))),
+    Some(GoogleError {
+      code: 404,
+      reason: "notFound".to_string(),
+      message: "Nope".to_string(),
+    })
+  );
+  let status = r#"{"error": {"code": 400, "message": "Bad", "status": "INVALID_ARGUMENT"}}"#;
+  assert_eq!(parse_error(status).unwrap().reason, "INVALID_ARGUMENT");
+  assert_eq!(parse_error("connection reset"), None);
+}
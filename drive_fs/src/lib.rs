@@ -1,11 +1,17 @@
-use log::{debug, info};
+use api_retry::Retryable;
+use log::{debug, info, warn};
+use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::path::Path;
 
+pub use api_retry::RetryPolicy;
+pub use models::DriveError;
 pub use wrapi::{AuthMethod, WrapiApi, WrapiError, WrapiResult};
 pub mod models;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FileNode {
   id: String,
   name: String,
@@ -13,41 +19,72 @@ pub struct FileNode {
   children: Vec<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FileCache {
   root_id: String,
   // Organize the files by parents/children for easy traversal to root
   graph_cache: HashMap<String, FileNode>,
-  // Lookup a File by its path, which can be calculated from the graph
-  path_cache: HashMap<String, String>,
+  // Lookup the folders at a path, which can be calculated from the graph. Drive allows several
+  // folders with the same name in one place, so a path can point to more than one
+  #[serde(skip)]
+  path_cache: HashMap<String, Vec<String>>,
+  // Where in the drive's list of changes the cache was last brought up to date
+  page_token: Option<String>,
+  // The shared drive being cached, or None for My Drive
+  drive_id: Option<String>,
 }
 
 impl FileCache {
   fn empty() -> FileCache {
+    FileCache::for_drive(None)
+  }
+
+  /// An empty cache for a shared drive, whose root folder has the same ID as the drive
+  fn for_drive(drive_id: Option<String>) -> FileCache {
     FileCache {
-      root_id: "root".to_string(),
+      root_id: drive_id.clone().unwrap_or_else(|| "root".to_string()),
       graph_cache: HashMap::new(),
       path_cache: HashMap::new(),
+      page_token: None,
+      drive_id,
     }
   }
 
-  // TODO: add clear cache function
+  /// Forget every folder, going back to the state before the cache was loaded
+  fn clear(&mut self) {
+    *self = FileCache::for_drive(self.drive_id.clone());
+  }
 
   /// Get all the directories loaded into the cache so we can do a quick find
-  fn load(&self, api: std::cell::RefMut<impl wrapi::WrapiApi>) -> Result<FileCache, WrapiError> {
+  fn load(&self, api: &DriveApi) -> Result<FileCache, DriveError> {
     info!("Loading the cache");
+    // Mark the point in the changes first, so anything changed while loading is picked up by refresh
+    let start: Box<models::StartPageToken> = api.call(
+      "start_page_token",
+      models::StartPageTokenRequest {
+        drive_id: self.drive_id.clone(),
+      },
+    )?;
     let request = models::FileRequest {
-      parent_id: "root".to_string(),
+      parent_id: None,
       filters: vec![models::FileFilter::Type(models::MimeType::Folder)],
       opts: vec![],
+      page_token: None,
+      drive_id: self.drive_id.clone(),
     };
-    let result: Box<models::FileResult> = api.call("find", request)?;
+    // My Drive's cache leaves out the folders of shared drives, which have their own roots
+    let folders = FilePages::new(api, request)
+      .filter(|folder| match folder {
+        Ok(folder) => folder.drive_id == self.drive_id,
+        Err(_) => true,
+      })
+      .collect::<Result<Vec<_>, _>>()?;
     let mut graph: HashMap<String, FileNode> = HashMap::new();
 
     // A disposable hash to find the root node (since it has an ID, but does not show up in the query)
     // The root will be the only entry with a parent count of 0
     let mut root_finder: HashMap<String, usize> = HashMap::new();
-    for file in result.files {
+    for file in folders {
       let id = file.id.unwrap();
       let name = file.name.unwrap().clone();
 
@@ -101,17 +138,21 @@ impl FileCache {
       let count = root_finder.get(key).unwrap();
       match (acc, count) {
         (Ok(None), 0) => Ok(Some(key.clone())),
-        (Ok(Some(x)), 0) => Err(format!(
+        (Ok(Some(x)), 0) => Err(DriveError::Invalid(format!(
           "Found multiple values without parents: ({}, {})",
           x, key
-        )),
+        ))),
         (err, _) => err,
       }
     })?;
 
-    let root_id = match root {
-      Some(x) => x,
-      None => Err("Did not find a root path without parents")?,
+    let root_id = match (root, &self.drive_id) {
+      (Some(x), _) => x,
+      // A shared drive without any folders
+      (None, Some(drive_id)) => drive_id.clone(),
+      (None, None) => Err(DriveError::NotFound(
+        "Did not find a root path without parents".to_string(),
+      ))?,
     };
 
     // Unravel the graph
@@ -131,7 +172,7 @@ impl FileCache {
           children.push(key);
           FileNode {
             id: acc.id,
-            name: value.name.clone(),
+            name: acc.name,
             children: children,
             parents: acc.parents,
           }
@@ -139,24 +180,138 @@ impl FileCache {
         false => acc,
       },
     );
+    graph.insert(root_id.clone(), root_node);
+
+    let mut cache = FileCache {
+      root_id: root_id.clone(),
+      graph_cache: graph,
+      path_cache: HashMap::new(),
+      page_token: Some(start.start_page_token),
+      drive_id: self.drive_id.clone(),
+    };
+    cache.path_cache = cache.build_paths()?;
+    Ok(cache)
+  }
+
+  /// Bring the cache up to date by applying only what changed in Drive since the last load or refresh
+  ///
+  /// A cache that was never loaded has nothing to start from, so it gets a full load instead.
+  fn refresh(&mut self, api: &DriveApi) -> Result<(), DriveError> {
+    let mut page_token = match &self.page_token {
+      Some(page_token) => page_token.clone(),
+      None => {
+        *self = self.load(api)?;
+        return Ok(());
+      }
+    };
+
+    // Fetch every page before touching the graph, so a failed call leaves the cache as it was
+    let mut changes = vec![];
+    loop {
+      let page: Box<models::ChangeList> = api.call(
+        "changes",
+        models::ChangesRequest {
+          page_token: page_token.clone(),
+          drive_id: self.drive_id.clone(),
+        },
+      )?;
+      let page = *page;
+      debug!("Fetched a page of {} changes", page.changes.len());
+      changes.extend(page.changes);
+      match (page.next_page_token, page.new_start_page_token) {
+        (Some(next), _) => page_token = next,
+        (None, Some(new_start)) => {
+          page_token = new_start;
+          break;
+        }
+        (None, None) => Err(DriveError::Invalid(
+          "The list of changes ended without a new start page token".to_string(),
+        ))?,
+      }
+    }
+
+    self.apply_changes(&changes)?;
+    info!("Applied {} changes to the cache", changes.len());
+    self.page_token = Some(page_token);
+    Ok(())
+  }
+
+  /// Apply a batch of changes from Drive to the graph, then rebuild the paths once
+  ///
+  /// The changes can come in any order. A folder whose parent hasn't shown up yet hangs off a
+  /// placeholder until it does.
+  pub fn apply_changes(&mut self, changes: &[models::Change]) -> Result<(), DriveError> {
+    let mut changed = false;
+    for change in changes {
+      changed |= self.apply_change(change)?;
+    }
+    if changed {
+      self.path_cache = self.build_paths()?;
+    }
+    Ok(())
+  }
+
+  /// The IDs of the cached folders at path
+  pub fn folder_ids(&self, path: &str) -> Vec<String> {
+    self.path_cache.get(path).cloned().unwrap_or_default()
+  }
+
+  /// Apply a single change to the graph, leaving the paths alone. Returns whether the graph changed
+  fn apply_change(&mut self, change: &models::Change) -> Result<bool, DriveError> {
+    let file_id = match (change.change_type.as_deref(), &change.file_id) {
+      (None, Some(file_id)) | (Some("file"), Some(file_id)) => file_id,
+      // A shared drive itself changed, which holds no folders of its own
+      _ => return Ok(false),
+    };
+    let known = self.graph_cache.contains_key(file_id);
+    match &change.file {
+      Some(file)
+        if !change.removed && file.trashed != Some(true) && file.drive_id == self.drive_id =>
+      {
+        if !file.is_folder() {
+          return Ok(false);
+        }
+        // Covers creates, renames and moves alike: detach from the old parents and attach again
+        if known {
+          self.unlink_folder(file_id);
+        }
+        self.link_folder(file)?;
+        Ok(true)
+      }
+      // Deleted, trashed, moved to another drive, or no longer visible to us
+      _ => {
+        if known {
+          self.drop_folder(file_id);
+        }
+        Ok(known)
+      }
+    }
+  }
 
+  /// Calculate the path of every folder reachable from the root of the graph
+  ///
+  /// A folder with several parents gets a path through each of them.
+  fn build_paths(&self) -> Result<HashMap<String, Vec<String>>, DriveError> {
     let mut path_map = HashMap::new();
     fn path_builder(
       cwd: String,
       file: FileNode,
       graph: &HashMap<String, FileNode>,
-      path_map: &mut HashMap<String, String>,
-    ) -> Result<(), WrapiError> {
-      path_map.entry(cwd.clone()).or_insert(file.id.clone());
+      path_map: &mut HashMap<String, Vec<String>>,
+    ) -> Result<(), DriveError> {
+      let ids = path_map.entry(cwd.clone()).or_default();
+      if !ids.contains(&file.id) {
+        ids.push(file.id.clone());
+      }
       match file.children.is_empty() {
         true => Ok(()),
         false => {
           for child in file.children.clone() {
             match graph.get(&child) {
-              None => Err(format!(
+              None => Err(DriveError::NotFound(format!(
                 "Path builder - could not find child '{}' in graph",
                 child
-              ))?,
+              )))?,
               Some(node) => path_builder(
                 match cwd.len() {
                   0 | 1 => format!("/{}", node.name),
@@ -172,128 +327,2783 @@ impl FileCache {
         }
       }
     }
-    path_builder("/".to_string(), root_node, &graph, &mut path_map)?;
-    // let dirs =
-    // If Parent exists, append to vec
 
-    // println!("path_map:\n{:#?}", path_map);
-    // Load them into the path cache
-    Ok(FileCache {
-      root_id: root_id.clone(),
-      graph_cache: graph,
-      path_cache: path_map,
+    match self.graph_cache.get(&self.root_id) {
+      Some(root) => path_builder(
+        "/".to_string(),
+        root.clone(),
+        &self.graph_cache,
+        &mut path_map,
+      )?,
+      None => {
+        path_map.insert("/".to_string(), vec![self.root_id.clone()]);
+      }
+    };
+    Ok(path_map)
+  }
+
+  /// Every path to a cached folder, one for each way up through its parents to the root
+  fn paths_of(&self, id: &str) -> Vec<String> {
+    if id == self.root_id || id == "root" {
+      return vec!["/".to_string()];
+    }
+    let node = match self.graph_cache.get(id) {
+      Some(node) => node,
+      None => return vec![],
+    };
+    node
+      .parents
+      .iter()
+      .flat_map(|parent| self.paths_of(parent))
+      .map(|dir| join_path(&dir, &node.name))
+      .collect()
+  }
+
+  /// The cached folders named name directly inside of parent_id, or None if the parent isn't cached
+  fn children_named(&self, parent_id: &str, name: &str) -> Option<Vec<String>> {
+    let parent_id = match parent_id {
+      "root" => &self.root_id,
+      id => id,
+    };
+    self.graph_cache.get(parent_id).map(|parent| {
+      parent
+        .children
+        .iter()
+        .filter(|child| match self.graph_cache.get(*child) {
+          Some(node) => node.name == name,
+          None => false,
+        })
+        .cloned()
+        .collect()
+    })
+  }
+
+  /// Rebuild a folder's metadata from the graph
+  ///
+  /// Only folders with a path are answered for. Placeholders and folders left under something in
+  /// the trash are looked up in Drive instead.
+  fn get_folder(&self, id: &str) -> Option<models::File> {
+    if id == self.root_id || self.paths_of(id).is_empty() {
+      return None;
+    }
+    self.graph_cache.get(id).map(|node| models::File {
+      id: Some(node.id.clone()),
+      name: Some(node.name.clone()),
+      parents: Some(node.parents.clone()),
+      mime_type: Some(models::MimeType::Folder),
+      ..Default::default()
+    })
+  }
+
+  /// Add a newly found or created folder to the graph, extending the paths with just its own
+  fn add_folder(&mut self, folder: &models::File) -> Result<(), DriveError> {
+    self.link_folder(folder)?;
+    if let Some(id) = &folder.id {
+      self.add_paths(id);
+    }
+    Ok(())
+  }
+
+  /// Swap the "root" alias of a cache that was never loaded for the real ID of the root folder, so
+  /// the folders Drive says are in it can be given paths
+  fn set_root_id(&mut self, id: &str) {
+    if self.root_id == "root" && self.drive_id.is_none() {
+      self.root_id = id.to_string();
+    }
+  }
+
+  /// Put the paths of a folder and everything under it into the path cache, working from the paths
+  /// of its parents
+  fn add_paths(&mut self, id: &str) {
+    let mut to_add = vec![id.to_string()];
+    while let Some(current) = to_add.pop() {
+      for path in self.paths_of(&current) {
+        let ids = self.path_cache.entry(path).or_default();
+        if !ids.contains(&current) {
+          ids.push(current.clone());
+        }
+      }
+      if let Some(node) = self.graph_cache.get(&current) {
+        to_add.extend(node.children.clone());
+      }
+    }
+  }
+
+  /// Take the folders in ids out of the path cache
+  fn forget_paths(&mut self, ids: &HashSet<String>) {
+    for path_ids in self.path_cache.values_mut() {
+      path_ids.retain(|id| !ids.contains(id));
+    }
+    self.path_cache.retain(|_, path_ids| !path_ids.is_empty());
+  }
+
+  /// The IDs of a cached folder and every folder under it
+  fn subtree(&self, id: &str) -> HashSet<String> {
+    let mut found = HashSet::new();
+    let mut to_check = vec![id.to_string()];
+    while let Some(current) = to_check.pop() {
+      if let Some(node) = self.graph_cache.get(&current) {
+        if found.insert(current) {
+          to_check.extend(node.children.clone());
+        }
+      }
+    }
+    found
+  }
+
+  /// Put a folder in the graph under each of its parents, keeping any children it already has
+  ///
+  /// Parents that aren't in the graph yet get a placeholder, the same as in load, which the parent
+  /// fills in when it arrives.
+  fn link_folder(&mut self, folder: &models::File) -> Result<(), DriveError> {
+    let id = folder.id.clone().ok_or_else(|| missing_id("folder"))?;
+    let name = folder
+      .name
+      .clone()
+      .ok_or_else(|| DriveError::Invalid("Drive returned a folder without a name".to_string()))?;
+    let parents = folder.parents.clone().unwrap_or_default();
+
+    for parent in &parents {
+      let node = self
+        .graph_cache
+        .entry(parent.clone())
+        .or_insert_with(|| FileNode {
+          id: parent.clone(),
+          name: "Unknown: Parent Insert".to_string(),
+          parents: vec![],
+          children: vec![],
+        });
+      if !node.children.contains(&id) {
+        node.children.push(id.clone());
+      }
+    }
+    // The subfolders of a folder coming back from the trash still list it as their parent
+    let children = match self.graph_cache.get(&id) {
+      Some(node) => node.children.clone(),
+      None => self
+        .graph_cache
+        .values()
+        .filter(|node| node.parents.contains(&id))
+        .map(|node| node.id.clone())
+        .collect(),
+    };
+    self.graph_cache.insert(
+      id.clone(),
+      FileNode {
+        id,
+        name,
+        parents,
+        children,
+      },
+    );
+    Ok(())
+  }
+
+  /// Apply a rename or move of a cached folder, keeping its children attached
+  fn move_folder(&mut self, folder: &models::File) -> Result<(), DriveError> {
+    let id = folder.id.clone().ok_or_else(|| missing_id("folder"))?;
+    self.unlink_folder(&id);
+    self.link_folder(folder)?;
+    let moved = self.subtree(&id);
+    self.forget_paths(&moved);
+    self.add_paths(&id);
+    Ok(())
+  }
+
+  /// Detach a folder from its current parents, leaving the folder itself in the graph
+  fn unlink_folder(&mut self, id: &str) {
+    if let Some(node) = self.graph_cache.get(id).cloned() {
+      for parent in &node.parents {
+        if let Some(parent_node) = self.graph_cache.get_mut(parent) {
+          parent_node.children.retain(|child| child != id);
+        }
+      }
+    }
+  }
+
+  /// Check if ancestor_id is the folder id or one of the folders above it
+  fn is_ancestor(&self, ancestor_id: &str, id: &str) -> bool {
+    let mut to_check = vec![id.to_string()];
+    while let Some(current) = to_check.pop() {
+      if current == ancestor_id {
+        return true;
+      }
+      if let Some(node) = self.graph_cache.get(&current) {
+        to_check.extend(node.parents.clone());
+      }
+    }
+    false
+  }
+
+  /// Drop a folder from the graph, and it and everything under it from the paths
+  fn remove_folder(&mut self, id: &str) -> Result<(), DriveError> {
+    let removed = self.subtree(id);
+    if self.drop_folder(id) {
+      self.forget_paths(&removed);
+    }
+    Ok(())
+  }
+
+  /// Take a folder out of the graph. Returns false if it wasn't cached
+  ///
+  /// Drive only reports the change for the folder itself when it is trashed, so the folders under it
+  /// stay in the graph without a path. That way restoring it brings them back, and deleting it for
+  /// good sends a change for each of them.
+  fn drop_folder(&mut self, id: &str) -> bool {
+    let node = match self.graph_cache.remove(id) {
+      Some(node) => node,
+      None => return false,
+    };
+    for parent in &node.parents {
+      if let Some(parent_node) = self.graph_cache.get_mut(parent) {
+        parent_node.children.retain(|child| child != id);
+      }
+    }
+    true
+  }
+}
+
+/// Bump this whenever FileCache changes shape, so caches saved by older versions are not misread
+const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// What save_cache writes to disk
+#[derive(Serialize, Deserialize)]
+struct SavedCache {
+  version: u32,
+  cache: FileCache,
+}
+
+/// Just enough of a saved cache to check its version before reading the rest
+#[derive(Deserialize)]
+struct SavedCacheVersion {
+  version: Option<u32>,
+}
+
+impl FileCache {
+  /// Write the folder graph and page token to path. The paths are left out since they are rebuilt
+  fn save(&self, path: &Path) -> Result<(), DriveError> {
+    let saved = SavedCache {
+      version: CACHE_FORMAT_VERSION,
+      cache: self.clone(),
+    };
+    // Write next to the cache and swap it in, so a failed save leaves the old cache whole
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = std::path::PathBuf::from(temp_path);
+    let write_error = |err: std::io::Error| {
+      DriveError::Io(format!(
+        "Could not write the cache file '{}': {}",
+        temp_path.display(),
+        err
+      ))
+    };
+
+    let file = std::fs::File::create(&temp_path).map_err(write_error)?;
+    let mut writer = std::io::BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &saved)?;
+    writer.flush().map_err(write_error)?;
+    std::fs::rename(&temp_path, path).map_err(|err| {
+      DriveError::Io(format!(
+        "Could not replace the cache file '{}': {}",
+        path.display(),
+        err
+      ))
+    })
+  }
+
+  /// Read a cache written by save, rejecting files from any other format version
+  fn restore(path: &Path) -> Result<FileCache, DriveError> {
+    let content = std::fs::read_to_string(path).map_err(|err| {
+      DriveError::Io(format!(
+        "Could not read the cache file '{}': {}",
+        path.display(),
+        err
+      ))
+    })?;
+    let version: SavedCacheVersion = serde_json::from_str(&content)?;
+    match version.version {
+      Some(CACHE_FORMAT_VERSION) => (),
+      Some(other) => Err(DriveError::Unsupported(format!(
+        "The cache file '{}' is format version {}, but only version {} can be read",
+        path.display(),
+        other,
+        CACHE_FORMAT_VERSION
+      )))?,
+      None => Err(DriveError::Invalid(format!(
+        "'{}' is not a saved cache: it has no format version",
+        path.display()
+      )))?,
+    }
+
+    let saved: SavedCache = serde_json::from_str(&content)?;
+    let mut cache = saved.cache;
+    cache.path_cache = cache.build_paths()?;
+    Ok(cache)
+  }
+}
+
+/// Path segments written as `id:<fileId>` pick a file by its ID instead of its name, for when a
+/// folder holds more than one item with the same name
+const ID_SEGMENT_PREFIX: &str = "id:";
+
+/// Paths starting with `//<drive name>` are inside of that shared drive
+const SHARED_DRIVE_PREFIX: &str = "//";
+
+/// The file ID in a path segment like `id:<fileId>`
+fn id_segment(segment: &str) -> Option<&str> {
+  segment.strip_prefix(ID_SEGMENT_PREFIX)
+}
+
+/// The error for a path that matches more than one file
+fn ambiguous_path(path: &str, ids: &[String]) -> DriveError {
+  DriveError::Ambiguous {
+    path: path.to_string(),
+    ids: ids.to_vec(),
+  }
+}
+
+/// The error for an export Drive can't do, listing the formats it can
+fn unsupported_export(
+  from: &Option<models::MimeType>,
+  to: &models::MimeType,
+  allowed: &[models::MimeType],
+) -> DriveError {
+  DriveError::Unsupported(format!(
+    "cannot export {:?} as {:?}. It can be exported as {:?}",
+    from, to, allowed
+  ))
+}
+
+/// The error for a file or folder Drive sent back without its ID
+fn missing_id(kind: &str) -> DriveError {
+  DriveError::Invalid(format!("Drive returned a {} without an ID", kind))
+}
+
+/// Add name to the end of the directory dir
+fn join_path(dir: &str, name: &str) -> String {
+  match dir.trim_end_matches('/') {
+    "" => format!("/{}", name),
+    dir => format!("{}/{}", dir, name),
+  }
+}
+
+/// Split a path into its parent directory and the name of the last item, ignoring trailing slashes
+///
+/// The root of a shared drive, `//<drive name>`, has no name and is its own directory, like "/".
+fn split_path(path: &str) -> (&str, &str) {
+  let trimmed = path.trim_end_matches('/');
+  if let Some(drive) = trimmed.strip_prefix(SHARED_DRIVE_PREFIX) {
+    if !drive.contains('/') {
+      return (trimmed, "");
+    }
+  }
+  match trimmed.rfind('/') {
+    Some(i) => (&trimmed[..i], &trimmed[i + 1..]),
+    None => ("/", trimmed),
+  }
+}
+
+/// How many times a resumable upload retries a chunk before giving up
+const MAX_UPLOAD_RETRIES: usize = 3;
+/// The default amount of content sent per resumable upload request
+const DEFAULT_CHUNK_SIZE: usize = 40 * models::UPLOAD_CHUNK_UNIT;
+
+/// PUT raw bytes to uri, returning the headers and body of the response. No auth header is sent
+fn put_bytes(
+  uri: &str,
+  headers: Vec<(String, String)>,
+  content: Vec<u8>,
+) -> Result<Response, WrapiError> {
+  use hyper::rt::{Future, Stream};
+
+  let connector = hyper_tls::HttpsConnector::new(1)
+    .map_err(|err| WrapiError::General(format!("Could not set up TLS: {}", err)))?;
+  let client = hyper::Client::builder().build::<_, hyper::Body>(connector);
+  let mut http_request = hyper::Request::put(uri);
+  for (name, value) in &headers {
+    http_request.header(name.as_str(), value.as_str());
+  }
+  let http_request = http_request
+    .body(hyper::Body::from(content))
+    .map_err(|err| WrapiError::General(format!("Bad upload request: {}", err)))?;
+  let response = client.request(http_request).and_then(|response| {
+    let (parts, body) = response.into_parts();
+    body.concat2().map(|body| (parts, body))
+  });
+
+  let mut runtime = tokio::runtime::Runtime::new()
+    .map_err(|err| WrapiError::General(format!("Could not start the upload runtime: {}", err)))?;
+  let (parts, body) = runtime
+    .block_on(response)
+    .map_err(|err| WrapiError::General(format!("Error sending to '{}': {}", uri, err)))?;
+  let headers: Vec<(String, String)> = parts
+    .headers
+    .iter()
+    .map(|(name, value)| {
+      (
+        name.to_string(),
+        String::from_utf8_lossy(value.as_bytes()).to_string(),
+      )
+    })
+    .collect();
+  if parts.status.is_client_error() || parts.status.is_server_error() {
+    api_retry::check_response(&headers, &body)?;
+    return Err(api_retry::http_error(parts.status.as_u16(), &body));
+  }
+  Ok((headers, body.to_vec()))
+}
+
+/// Read up to size bytes, only returning less at the end of the stream
+fn read_chunk(reader: &mut dyn Read, size: usize) -> Result<Vec<u8>, DriveError> {
+  let mut chunk = vec![];
+  (&mut *reader)
+    .take(size as u64)
+    .read_to_end(&mut chunk)
+    .map_err(|err| DriveError::Io(format!("Error reading the upload content: {}", err)))?;
+  Ok(chunk)
+}
+
+/// The content of an upload, with its local file name and size when they are known
+struct OpenSource {
+  name: Option<String>,
+  size: Option<u64>,
+  reader: Box<dyn Read>,
+}
+
+/// Open the content of an upload
+fn open_source(source: models::UploadSource) -> Result<OpenSource, DriveError> {
+  match source {
+    models::UploadSource::Path(path) => {
+      let file = std::fs::File::open(&path).map_err(|err| {
+        DriveError::Io(format!(
+          "Could not open '{}' to upload: {}",
+          path.display(),
+          err
+        ))
+      })?;
+      let size = file.metadata().map(|meta| meta.len()).ok();
+      let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+      Ok(OpenSource {
+        name,
+        size,
+        reader: Box::new(file),
+      })
+    }
+    models::UploadSource::Reader(reader) => Ok(OpenSource {
+      name: None,
+      size: None,
+      reader,
+    }),
+  }
+}
+
+/// The resumable chunk size from the options, rounded down to a multiple of 256KB
+fn chunk_size(opts: &[models::UploadOpts]) -> usize {
+  let size = opts
+    .iter()
+    .find_map(|opt| match opt {
+      models::UploadOpts::ChunkSize(size) => Some(*size),
+      _ => None,
+    })
+    .unwrap_or(DEFAULT_CHUNK_SIZE);
+  std::cmp::max(
+    size - size % models::UPLOAD_CHUNK_UNIT,
+    models::UPLOAD_CHUNK_UNIT,
+  )
+}
+
+/// The amount of content fetched per request when downloading
+const DOWNLOAD_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How many folder IDs go into a single `'id' in parents` search, to keep the query a sane length
+const PARENTS_PER_QUERY: usize = 50;
+
+/// Split a search across many folders into a few queries, each covering a chunk of the parents
+fn parent_requests(
+  parent_ids: &[String],
+  filters: Vec<models::FileFilter>,
+  opts: Vec<models::FileOpts>,
+) -> Vec<models::FileRequest> {
+  parent_ids
+    .chunks(PARENTS_PER_QUERY)
+    .map(|chunk| {
+      let mut filters = filters.clone();
+      filters.push(models::FileFilter::Parent(models::Filter::Any(
+        chunk
+          .iter()
+          .map(|id| models::Filter::Equals(id.clone()))
+          .collect(),
+      )));
+      models::FileRequest {
+        parent_id: None,
+        filters,
+        opts: opts.clone(),
+        page_token: None,
+        drive_id: None,
+      }
     })
+    .collect()
+}
+
+/// Lazily pages through the results of one or more file queries, one file at a time
+///
+/// The next page is only requested from Drive once the current one has been used up. Files found
+/// by more than one of the queries are only returned once. After an error, the iterator ends.
+pub struct FilePages<'a> {
+  api: &'a DriveApi,
+  // The queries still to run, with the one being paged through at the front
+  requests: VecDeque<models::FileRequest>,
+  files: std::vec::IntoIter<models::File>,
+  seen: HashSet<String>,
+}
+
+impl<'a> FilePages<'a> {
+  fn new(api: &'a DriveApi, request: models::FileRequest) -> FilePages<'a> {
+    FilePages::from_requests(api, vec![request])
+  }
+
+  fn from_requests(api: &'a DriveApi, requests: Vec<models::FileRequest>) -> FilePages<'a> {
+    FilePages {
+      api,
+      requests: requests.into_iter().collect(),
+      files: vec![].into_iter(),
+      seen: HashSet::new(),
+    }
+  }
+}
+
+impl<'a> Iterator for FilePages<'a> {
+  type Item = Result<models::File, DriveError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(file) = self.files.next() {
+        if let Some(id) = &file.id {
+          if !self.seen.insert(id.clone()) {
+            continue;
+          }
+        }
+        return Some(Ok(file));
+      }
+
+      let request = self.requests.pop_front()?;
+      let page: Result<Box<models::FileResult>, DriveError> =
+        self.api.call("find", request.clone());
+      match page {
+        Ok(page) => {
+          let page = *page;
+          debug!("Fetched a page of {} files", page.files.len());
+          if let Some(page_token) = page.next_page_token {
+            self.requests.push_front(models::FileRequest {
+              page_token: Some(page_token),
+              ..request
+            });
+          }
+          self.files = page.files.into_iter();
+        }
+        Err(err) => {
+          self.requests.clear();
+          return Some(Err(err));
+        }
+      }
+    }
+  }
+}
+
+/// The endpoints that make something new each time they are called, so a server error part way
+/// through isn't retried unless the RetryPolicy allows it
+const NON_IDEMPOTENT: &[&str] = &[
+  "create",
+  "copy",
+  "upload",
+  "create_permission",
+  "create_comment",
+  "create_reply",
+];
+
+const FILES_URL: &str = "https://www.googleapis.com/drive/v3/files";
+const CHANGES_URL: &str = "https://www.googleapis.com/drive/v3/changes";
+const DRIVES_URL: &str = "https://www.googleapis.com/drive/v3/drives";
+const UPLOAD_URL: &str = "https://www.googleapis.com/upload/drive/v3/files";
+
+/// The base URL the endpoint called name is registered with
+fn endpoint_url(name: &str) -> &'static str {
+  match name {
+    "upload" => UPLOAD_URL,
+    "start_page_token" | "changes" => CHANGES_URL,
+    "drives" => DRIVES_URL,
+    _ => FILES_URL,
+  }
+}
+
+/// A request as it would be sent to Drive, handed to a Responder
+#[derive(Clone, Debug)]
+pub struct Call {
+  /// The name of the endpoint, like "find" or "upload". Chunks of a resumable upload are
+  /// "upload_chunk"
+  pub endpoint: String,
+  pub uri: String,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+/// The headers and body of a response
+pub type Response = (Vec<(String, String)>, Vec<u8>);
+
+/// Answers a Call in place of Drive
+pub type Responder = Box<dyn Fn(&Call) -> Result<Response, WrapiError>>;
+
+/// Where the calls go
+enum Transport {
+  Wrapi(RefCell<wrapi::API>),
+  Responder(Responder),
+}
+
+impl std::fmt::Debug for Transport {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Transport::Wrapi(api) => api.fmt(f),
+      Transport::Responder(_) => write!(f, "Responder"),
+    }
+  }
+}
+
+/// The API along with the policy for retrying its calls
+#[derive(Debug)]
+struct DriveApi {
+  transport: Transport,
+  retry: RetryPolicy,
+}
+
+impl DriveApi {
+  fn call<T: WrapiResult, R: wrapi::WrapiRequest + Clone>(
+    &self,
+    name: &str,
+    request: R,
+  ) -> Result<Box<T>, DriveError> {
+    let idempotent = !NON_IDEMPOTENT.contains(&name);
+    Ok(self.retry.run(name, idempotent, || match &self.transport {
+      Transport::Wrapi(api) => api.borrow_mut().call(name, request.clone()),
+      Transport::Responder(respond) => {
+        let call = Call {
+          endpoint: name.to_string(),
+          uri: request.build_uri(endpoint_url(name))?,
+          headers: request.build_headers()?,
+          body: request.build_body()?.into_bytes(),
+        };
+        let (headers, body) = respond(&call)?;
+        T::parse(headers, body)
+      }
+    })?)
+  }
+
+  /// PUT a chunk of raw bytes to a resumable upload session
+  fn send_chunk(
+    &self,
+    request: &models::UploadChunkRequest,
+  ) -> Result<models::UploadStatus, WrapiError> {
+    let headers = vec![("Content-Range".to_string(), request.content_range())];
+    let (headers, body) = match &self.transport {
+      Transport::Wrapi(_) => put_bytes(&request.session_uri, headers, request.content.clone())?,
+      Transport::Responder(respond) => respond(&Call {
+        endpoint: "upload_chunk".to_string(),
+        uri: request.session_uri.clone(),
+        headers,
+        body: request.content.clone(),
+      })?,
+    };
+    Ok(*models::UploadStatus::parse(headers, body)?)
   }
 }
 
 /// A struct to contain the API and link all the calls to
 #[derive(Debug)]
 pub struct DriveFS {
-  api: RefCell<wrapi::API>,
+  api: DriveApi,
   cache: FileCache,
+  // The real ID behind the "root" alias, once Drive has been asked for it
+  root_id: RefCell<Option<String>>,
+  // The shared drives as of the last time they were listed
+  drives: RefCell<Vec<models::SharedDrive>>,
+  // Treat shortcuts like symlinks, going through to what they point to
+  follow_shortcuts: bool,
 }
 
 impl DriveFS {
   pub fn build(auth: wrapi::AuthMethod) -> DriveFS {
-    let api = wrapi::API::new(auth.clone()).add_endpoint(
-      "find".to_string(),
-      wrapi::Endpoint {
-        base_url: "https://www.googleapis.com/drive/v3/files",
-        auth_method: auth.clone(),
-        request_method: wrapi::RequestMethod::GET,
-        scopes: vec!["https://www.googleapis.com/auth/drive"],
-        request_mime_type: wrapi::MimeType::Json,
-        response_mime_type: wrapi::MimeType::Json,
-      },
-    );
+    let endpoint = |base_url, request_method| wrapi::Endpoint {
+      base_url,
+      auth_method: auth.clone(),
+      request_method,
+      scopes: vec!["https://www.googleapis.com/auth/drive"],
+      request_mime_type: wrapi::MimeType::Json,
+      response_mime_type: wrapi::MimeType::Json,
+    };
+    let api = wrapi::API::new(auth.clone())
+      .add_endpoint(
+        "find".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "create".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::POST),
+      )
+      .add_endpoint(
+        "get".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "update".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::PATCH),
+      )
+      .add_endpoint(
+        "delete".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::DELETE),
+      )
+      .add_endpoint(
+        "copy".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::POST),
+      )
+      .add_endpoint(
+        "download".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "export".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "upload".to_string(),
+        endpoint(UPLOAD_URL, wrapi::RequestMethod::POST),
+      )
+      .add_endpoint(
+        "start_page_token".to_string(),
+        endpoint(CHANGES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "changes".to_string(),
+        endpoint(CHANGES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "drives".to_string(),
+        endpoint(DRIVES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "permissions".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "create_permission".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::POST),
+      )
+      .add_endpoint(
+        "update_permission".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::PATCH),
+      )
+      .add_endpoint(
+        "delete_permission".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::DELETE),
+      )
+      .add_endpoint(
+        "revisions".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "get_revision".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "download_revision".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "export_revision".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "update_revision".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::PATCH),
+      )
+      .add_endpoint(
+        "delete_revision".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::DELETE),
+      )
+      .add_endpoint(
+        "comments".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::GET),
+      )
+      .add_endpoint(
+        "create_comment".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::POST),
+      )
+      .add_endpoint(
+        "create_reply".to_string(),
+        endpoint(FILES_URL, wrapi::RequestMethod::POST),
+      );
     let mut path_cache = HashMap::new();
     path_cache.insert("/", "root".to_string());
     path_cache.insert("", "root".to_string());
 
+    DriveFS::with_transport(Transport::Wrapi(RefCell::new(api)))
+  }
+
+  /// A DriveFS that hands every call to responder instead of sending it to Drive
+  ///
+  /// This is for testing code built on DriveFS without credentials or a network. The URIs are
+  /// built on the same base URLs as the real calls.
+  pub fn with_responder<F>(responder: F) -> DriveFS
+  where
+    F: Fn(&Call) -> Result<Response, WrapiError> + 'static,
+  {
+    DriveFS::with_transport(Transport::Responder(Box::new(responder)))
+  }
+
+  fn with_transport(transport: Transport) -> DriveFS {
     DriveFS {
-      api: RefCell::new(api),
+      api: DriveApi {
+        transport,
+        retry: RetryPolicy::default(),
+      },
       cache: FileCache::empty(),
+      root_id: RefCell::new(None),
+      drives: RefCell::new(vec![]),
+      follow_shortcuts: false,
     }
   }
 
-  pub fn load_cache(self) -> Result<DriveFS, WrapiError> {
-    let new_cache = self.cache.load(self.api.borrow_mut())?;
+  pub fn load_cache(self) -> Result<DriveFS, DriveError> {
+    let new_cache = self.cache.load(&self.api)?;
     Ok(DriveFS {
       api: self.api,
       cache: new_cache,
+      root_id: self.root_id,
+      drives: self.drives,
+      follow_shortcuts: self.follow_shortcuts,
     })
   }
 
-  // TODO: Simplify this. With the path cache, this should be a direct lookup but seems to be looking
-  fn get_path_id(&self, path: &str) -> Result<String, WrapiError> {
-    println!("Finding the ID for directory:\n{:#?}", path);
-    let parent_id = path.split("/").into_iter().fold(
-      Ok(("root".to_string(), "/".to_string())),
-      |acc: Result<(String, String), WrapiError>, x| {
-        let (current_id, cwd) = acc?;
-        match (cwd.as_ref(), x) {
-          ("/", "") => Ok((current_id, String::from(""))),
-          (_, "") => Err(WrapiError::Json(format!(
-            "In fold and found a double '/' in the path: {}",
-            x
-          ))),
-          (_, _) => {
-            let next_dir = format!("{}/{}", cwd, x);
-            match self.cache.path_cache.get(&next_dir[..]) {
-              Some(next_id) => Ok((next_id.clone(), next_dir)),
-              None => {
-                println!("{} not found in cache. Doing the lookup now", next_dir);
-                let request = models::FileRequest {
-                  parent_id: current_id.clone(),
-                  filters: vec![],
-                  opts: vec![],
-                };
-                let _result: Box<models::FileResult> =
-                  self.api.borrow_mut().call("find", request)?;
-                Ok(("NOT ROOT".to_string(), next_dir))
-              }
-            }
-          }
-        }
-      },
-    )?;
-    Ok(parent_id.0)
+  /// Retry failed calls to Drive with policy instead of the default of 5 tries with backoff.
+  /// RetryPolicy::none() turns retries off
+  pub fn retry_policy(mut self, policy: RetryPolicy) -> DriveFS {
+    self.api.retry = policy;
+    self
   }
 
-  pub fn ls(
-    &self,
-    path: &str,
-    _opts: Vec<models::FileOpts>,
-  ) -> Result<models::FileResult, WrapiError> {
-    // Recursive calls on the path to find the right one.
-    let pwd = self.get_path_id(path)?;
-    Err(WrapiError::General(format!(
-      "Exiting LS: Found PWD of {}",
-      pwd
-    )))
+  /// Follow shortcuts like symlinks: in the folders of a path, when listing with ls, and when
+  /// downloading or exporting a file. Removing, moving or copying a shortcut still acts on the
+  /// shortcut itself. Off by default
+  pub fn follow_shortcuts(mut self, follow: bool) -> DriveFS {
+    self.follow_shortcuts = follow;
+    self
   }
 
-  // TODO: Parent ID does not work. Make test to validate and verify
-  pub fn find(
-    self,
-    work_dir: &str,
-    filters: Vec<models::FileFilter>,
-    opts: Vec<models::FileOpts>,
-  ) -> Result<Box<models::FileResult>, WrapiError> {
-    let parent_id = self.get_path_id(work_dir)?;
-    debug!("parent_id:\n{:#?}", parent_id);
+  /// Work inside of a shared drive, picked by name or `id:<driveId>`, or go back to My Drive with None
+  ///
+  /// Paths starting with a single '/' are then inside of that drive, and the cache holds its
+  /// folders. The cache starts out empty, so follow this with load_cache.
+  pub fn select_drive(mut self, drive: Option<&str>) -> Result<DriveFS, DriveError> {
+    let drive_id = match drive {
+      Some(drive) => Some(self.drive_named(drive)?.id),
+      None => None,
+    };
+    info!("Using the drive {:?}", drive_id);
+    self.cache = FileCache::for_drive(drive_id);
+    Ok(self)
+  }
 
-    // TODO: LS each sub-directory
-    // Get all the files with the name
-    let request = models::FileRequest {
-      parent_id: parent_id,
-      filters: filters,
-      opts: opts,
+  /// List the shared drives the user is a member of
+  pub fn drives(&self) -> Result<Vec<models::SharedDrive>, DriveError> {
+    let mut drives = vec![];
+    let mut page_token = None;
+    loop {
+      let page: Box<models::DriveList> = self
+        .api
+        .call("drives", models::DriveListRequest { page_token })?;
+      let page = *page;
+      drives.extend(page.drives);
+      match page.next_page_token {
+        Some(next) => page_token = Some(next),
+        None => break,
+      }
+    }
+    *self.drives.borrow_mut() = drives.clone();
+    Ok(drives)
+  }
+
+  /// Find the shared drive called name, which can also be an `id:<driveId>`
+  ///
+  /// The drives from the last listing are checked first, and only listed again when none match.
+  fn drive_named(&self, name: &str) -> Result<models::SharedDrive, DriveError> {
+    let matching = |drives: &[models::SharedDrive]| -> Vec<models::SharedDrive> {
+      drives
+        .iter()
+        .filter(|drive| match id_segment(name) {
+          Some(id) => drive.id == id,
+          None => drive.name == name,
+        })
+        .cloned()
+        .collect()
     };
-    let result = self.api.borrow_mut().call("find", request)?;
+    let mut found = matching(&self.drives.borrow());
+    if found.is_empty() {
+      found = matching(&self.drives()?);
+    }
+    match found.len() {
+      0 => Err(DriveError::NotFound(format!("shared drive '{}'", name)))?,
+      1 => Ok(found.remove(0)),
+      _ => {
+        let ids: Vec<String> = found.into_iter().map(|drive| drive.id).collect();
+        Err(ambiguous_path(
+          &format!("{}{}", SHARED_DRIVE_PREFIX, name),
+          &ids,
+        ))
+      }
+    }
+  }
 
-    Ok(result)
+  /// Work out where a path starts. `//<drive name>/...` starts at the root of that shared drive and
+  /// anything else at the root of the drive in use. Returns the root's ID, the root as it is
+  /// written in paths, and the rest of the path
+  fn path_root<'p>(&self, path: &'p str) -> Result<(String, String, &'p str), DriveError> {
+    if !path.starts_with(SHARED_DRIVE_PREFIX) {
+      return Ok((self.cache.root_id.clone(), "/".to_string(), path));
+    }
+    let path = &path[SHARED_DRIVE_PREFIX.len()..];
+    let (name, rest) = match path.find('/') {
+      Some(i) => (&path[..i], &path[i..]),
+      None => (path, ""),
+    };
+    let drive = self.drive_named(name)?;
+    Ok((
+      drive.id,
+      format!("{}{}", SHARED_DRIVE_PREFIX, drive.name),
+      rest,
+    ))
   }
 
-  // pub fn mkdir(&self, path: &str, opts: ) {}
+  /// Start from a cache written by save_cache instead of walking the whole drive
+  ///
+  /// The cache is then brought up to date by applying the changes made since it was saved.
+  pub fn load_cache_from<P: AsRef<Path>>(mut self, path: P) -> Result<DriveFS, DriveError> {
+    info!("Loading the cache from {}", path.as_ref().display());
+    self.cache = FileCache::restore(path.as_ref())?;
+    self.cache.refresh(&self.api)?;
+    Ok(self)
+  }
+
+  /// Save the folder cache to path, so a later process can warm start with load_cache_from
+  pub fn save_cache<P: AsRef<Path>>(&self, path: P) -> Result<(), DriveError> {
+    self.cache.save(path.as_ref())
+  }
+
+  /// Update the folder cache with only the changes made in Drive since it was loaded or refreshed
+  ///
+  /// This is much cheaper than load_cache for a long running process. If the cache was never
+  /// loaded, it is loaded in full.
+  pub fn refresh_cache(&mut self) -> Result<(), DriveError> {
+    self.cache.refresh(&self.api)
+  }
+
+  /// Empty the folder cache. Call load_cache to fill it again
+  pub fn clear_cache(&mut self) {
+    self.cache.clear()
+  }
+
+  /// Find the ID of the folder at path
+  ///
+  /// Paths come straight from the cache when it has them, otherwise they are looked up in Drive a
+  /// folder at a time. A path matching more than one folder is an error, and any segment can be
+  /// an `id:<fileId>` to pick one.
+  fn get_path_id(&self, path: &str) -> Result<String, DriveError> {
+    let (root_id, root_path, rest) = self.path_root(path)?;
+    self.path_id_under(path, root_id, root_path, rest)
+  }
+
+  /// Find the ID of the folder at rest, the part of path after the root that path_root found
+  fn path_id_under(
+    &self,
+    path: &str,
+    root_id: String,
+    root_path: String,
+    rest: &str,
+  ) -> Result<String, DriveError> {
+    debug!("Finding the ID for directory: {}", path);
+    // Trailing slashes don't change the directory, so "/" is the root and "/foo/" is "/foo"
+    let rest = match rest.trim_end_matches('/') {
+      "" => return Ok(root_id),
+      trimmed => trimmed,
+    };
+    if root_path == "/" {
+      match self.cache.path_cache.get(rest) {
+        Some(ids) if ids.len() == 1 => return Ok(ids[0].clone()),
+        Some(ids) if ids.len() > 1 => return Err(ambiguous_path(path, ids)),
+        _ => (),
+      }
+    }
+
+    let segments: Vec<&str> = rest.trim_start_matches('/').split('/').collect();
+    let mut current_id = root_id;
+    let mut cwd = root_path;
+    for segment in segments {
+      if segment.is_empty() {
+        Err(DriveError::Invalid(format!(
+          "Found a double '/' in the path: {}",
+          path
+        )))?;
+      }
+      cwd = join_path(&cwd, segment);
+      current_id = match self.child_folder(&current_id, segment, &cwd)? {
+        Some(folder) => folder.id.ok_or_else(|| missing_id("folder"))?,
+        None => Err(DriveError::NotFound(cwd.clone()))?,
+      };
+    }
+    Ok(current_id)
+  }
+
+  /// Look up the folder called name (or the `id:<fileId>` segment) inside of parent_id
+  ///
+  /// The cache answers when it knows the parent, falling back to Drive when the folder isn't there.
+  /// cwd is the path so far, for error messages.
+  fn child_folder(
+    &self,
+    parent_id: &str,
+    name: &str,
+    cwd: &str,
+  ) -> Result<Option<models::File>, DriveError> {
+    if let Some(id) = id_segment(name) {
+      let folder = match self.cache.get_folder(id) {
+        Some(folder) => folder,
+        None => self.follow(self.get_by_id(id)?)?,
+      };
+      if !folder.is_folder() {
+        Err(DriveError::Invalid(format!("Not a directory: '{}'", cwd)))?;
+      }
+      return Ok(Some(folder));
+    }
+
+    if let Some(ids) = self.cache.children_named(parent_id, name) {
+      match ids.len() {
+        0 => (),
+        1 => return Ok(self.cache.get_folder(&ids[0])),
+        _ => return Err(ambiguous_path(cwd, &ids)),
+      }
+    }
+
+    debug!("{} not found in cache. Doing the lookup now", cwd);
+    let mut found = self.find_folders(parent_id, name)?;
+    match found.len() {
+      0 if self.follow_shortcuts => self.shortcut_folder(parent_id, name, cwd),
+      0 => Ok(None),
+      1 => Ok(Some(found.remove(0))),
+      _ => {
+        let ids: Vec<String> = found.into_iter().filter_map(|folder| folder.id).collect();
+        Err(ambiguous_path(cwd, &ids))
+      }
+    }
+  }
+
+  /// Look for a shortcut called name inside of parent_id, returning the folder it points to
+  fn shortcut_folder(
+    &self,
+    parent_id: &str,
+    name: &str,
+    cwd: &str,
+  ) -> Result<Option<models::File>, DriveError> {
+    let mut shortcuts: Vec<models::File> = self
+      .find_named(parent_id, name, vec![])?
+      .into_iter()
+      .filter(|file| file.is_shortcut())
+      .collect();
+    match shortcuts.len() {
+      0 => Ok(None),
+      1 => {
+        let target = self.follow(shortcuts.remove(0))?;
+        if !target.is_folder() {
+          Err(DriveError::Invalid(format!("Not a directory: '{}'", cwd)))?;
+        }
+        Ok(Some(target))
+      }
+      _ => {
+        let ids: Vec<String> = shortcuts.into_iter().filter_map(|file| file.id).collect();
+        Err(ambiguous_path(cwd, &ids))
+      }
+    }
+  }
+
+  /// Go through file to what it points to when it is a shortcut and shortcuts are being followed
+  ///
+  /// Drive doesn't let shortcuts point to other shortcuts, but a chain that comes back on itself is
+  /// an error rather than a hang.
+  fn follow(&self, file: models::File) -> Result<models::File, DriveError> {
+    let mut file = file;
+    let mut seen = HashSet::new();
+    while self.follow_shortcuts && file.is_shortcut() {
+      let target_id = match &file.shortcut_details {
+        Some(details) => details.target_id.clone(),
+        None => Err(DriveError::Invalid(format!(
+          "Shortcut {:?} has no target",
+          file.id
+        )))?,
+      };
+      if !seen.insert(target_id.clone()) {
+        Err(DriveError::Invalid(format!(
+          "Found a loop of shortcuts at {:?}",
+          file.id
+        )))?;
+      }
+      file = self.get_by_id(&target_id)?;
+    }
+    Ok(file)
+  }
+
+  /// The path of the file with the given ID, for showing IDs from a search to people
+  ///
+  /// A file with several parents has several paths, and this is the first of them. Use paths to
+  /// get them all.
+  pub fn path_of(&self, id: &str) -> Result<String, DriveError> {
+    self
+      .paths(id)?
+      .into_iter()
+      .next()
+      .ok_or_else(|| DriveError::NotFound(format!("a path to '{}'", id)))
+  }
+
+  /// Every path that leads to the file with the given ID
+  ///
+  /// A file in several folders has a path through each of them. The parents are walked up to the
+  /// root using the cache where possible, asking Drive about anything it doesn't have. Items in
+  /// another shared drive start with `//<drive name>`. Items with no visible parents, like files
+  /// shared with you, start at their topmost folder with no '/'.
+  pub fn paths(&self, id: &str) -> Result<Vec<String>, DriveError> {
+    self.walk_paths(id, &mut vec![], &mut HashMap::new())
+  }
+
+  /// The ID of the folder at "/". Parents from Drive use the real ID, never the "root" alias
+  fn root_id(&self) -> Result<String, DriveError> {
+    if self.cache.root_id != "root" {
+      return Ok(self.cache.root_id.clone());
+    }
+    if let Some(id) = self.root_id.borrow().as_ref() {
+      return Ok(id.clone());
+    }
+    let id = self
+      .get_by_id("root")?
+      .id
+      .ok_or_else(|| missing_id("root folder"))?;
+    *self.root_id.borrow_mut() = Some(id.clone());
+    Ok(id)
+  }
+
+  /// Build the paths to id, with below holding the IDs being walked so loops are caught, and known
+  /// remembering the paths already worked out for each folder
+  fn walk_paths(
+    &self,
+    id: &str,
+    below: &mut Vec<String>,
+    known: &mut HashMap<String, Vec<String>>,
+  ) -> Result<Vec<String>, DriveError> {
+    if id == "root" || id == self.root_id()? {
+      return Ok(vec!["/".to_string()]);
+    }
+    if let Some(paths) = known.get(id) {
+      return Ok(paths.clone());
+    }
+    if below.iter().any(|child| child == id) {
+      Err(DriveError::Invalid(format!(
+        "Found a loop in the parents of '{}'",
+        id
+      )))?;
+    }
+    let cached = self.cache.paths_of(id);
+    if !cached.is_empty() {
+      return Ok(cached);
+    }
+
+    let file = self.get_by_id(id)?;
+    let name = file.name.clone().unwrap_or_default();
+    let parents = file.parents.unwrap_or_default();
+    if parents.is_empty() {
+      // The root folder of a shared drive has the drive's ID
+      if file.drive_id.as_ref().map(|drive_id| drive_id == id) == Some(true) {
+        let drive_name = match self.drive_named(&format!("{}{}", ID_SEGMENT_PREFIX, id)) {
+          Ok(drive) => drive.name,
+          Err(DriveError::NotFound(_)) => name,
+          Err(err) => return Err(err),
+        };
+        return Ok(vec![format!("{}{}", SHARED_DRIVE_PREFIX, drive_name)]);
+      }
+      return Ok(vec![name]);
+    }
+
+    below.push(id.to_string());
+    let mut paths = vec![];
+    for parent in &parents {
+      for dir in self.walk_paths(parent, below, known)? {
+        paths.push(join_path(&dir, &name));
+      }
+    }
+    below.pop();
+    known.insert(id.to_string(), paths.clone());
+    Ok(paths)
+  }
+
+  /// Look up the files named name directly inside of the parent folder
+  fn find_named(
+    &self,
+    parent_id: &str,
+    name: &str,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<Vec<models::File>, DriveError> {
+    let request = models::FileRequest {
+      parent_id: Some(parent_id.to_string()),
+      filters: vec![models::FileFilter::Name(models::Filter::Equals(
+        name.to_string(),
+      ))],
+      opts,
+      page_token: None,
+      drive_id: None,
+    };
+    FilePages::new(&self.api, request).collect()
+  }
+
+  /// Find the file at path, which has to exist and be the only file with that name in its folder
+  fn get_file(&self, path: &str, opts: Vec<models::FileOpts>) -> Result<models::File, DriveError> {
+    let fields = opts.iter().find_map(|opt| match opt {
+      models::FileOpts::Fields(fields) => Some(fields.clone()),
+      _ => None,
+    });
+    let (root_id, root_path, rest) = self.path_root(path)?;
+    if rest.trim_matches('/').is_empty() {
+      return self.get_fields(&root_id, fields);
+    }
+    let (dir, name) = split_path(path);
+    if let Some(id) = id_segment(name) {
+      return self.get_fields(id, fields);
+    }
+
+    let dir_id = self.path_id_under(dir, root_id, root_path, split_path(rest).0)?;
+    let mut files = self.find_named(&dir_id, name, opts)?;
+    match files.len() {
+      0 => Err(DriveError::NotFound(path.to_string()))?,
+      1 => Ok(files.remove(0)),
+      _ => {
+        let ids: Vec<String> = files.into_iter().filter_map(|file| file.id).collect();
+        Err(ambiguous_path(path, &ids))
+      }
+    }
+  }
+
+  fn get_by_id(&self, id: &str) -> Result<models::File, DriveError> {
+    self.get_fields(id, None)
+  }
+
+  /// Fetch a file by its ID with only the fields asked for, or the usual ones for None
+  fn get_fields(
+    &self,
+    id: &str,
+    fields: Option<models::FieldMask>,
+  ) -> Result<models::File, DriveError> {
+    let request = models::GetRequest {
+      file_id: id.to_string(),
+      fields,
+    };
+    let result: Box<models::File> = self.api.call("get", request)?;
+    Ok(*result)
+  }
+
+  /// Get the metadata of a file: its size, checksum, times, owners, stars and properties
+  ///
+  /// Pass a FieldMask to only fetch some of that, or more. None fetches FieldMask::stat.
+  pub fn stat(
+    &self,
+    target: models::FileRef,
+    fields: Option<models::FieldMask>,
+  ) -> Result<models::File, DriveError> {
+    let fields = fields.unwrap_or_else(models::FieldMask::stat);
+    match target {
+      models::FileRef::Path(path) => self.get_file(&path, vec![models::FileOpts::Fields(fields)]),
+      models::FileRef::Id(id) => self.get_fields(&id, Some(fields)),
+    }
+  }
+
+  /// Set custom key/value properties on a file, which anyone can see and search for. A key set to
+  /// None is removed. Keys not mentioned are left alone
+  pub fn set_properties(
+    &self,
+    target: models::FileRef,
+    properties: HashMap<String, Option<String>>,
+  ) -> Result<models::File, DriveError> {
+    self.update_metadata(
+      target,
+      models::FileUpdate {
+        properties: Some(properties),
+        ..Default::default()
+      },
+    )
+  }
+
+  /// The same as set_properties, but for appProperties which only this app can see
+  pub fn set_app_properties(
+    &self,
+    target: models::FileRef,
+    properties: HashMap<String, Option<String>>,
+  ) -> Result<models::File, DriveError> {
+    self.update_metadata(
+      target,
+      models::FileUpdate {
+        app_properties: Some(properties),
+        ..Default::default()
+      },
+    )
+  }
+
+  fn update_metadata(
+    &self,
+    target: models::FileRef,
+    update: models::FileUpdate,
+  ) -> Result<models::File, DriveError> {
+    let request = models::UpdateRequest {
+      file_id: self.file_id(&target)?,
+      update,
+      add_parents: vec![],
+      remove_parents: vec![],
+    };
+    let updated: Box<models::File> = self.api.call("update", request)?;
+    Ok(*updated)
+  }
+
+  /// Look up the file being pointed to
+  fn resolve(
+    &self,
+    target: &models::FileRef,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<models::File, DriveError> {
+    match target {
+      models::FileRef::Path(path) => self.get_file(path, opts),
+      models::FileRef::Id(id) => self.get_by_id(id),
+    }
+  }
+
+  /// List the contents of a folder by its ID, including all sub-folders when Recursive(true)
+  fn list(
+    &self,
+    folder_id: &str,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<Vec<models::File>, DriveError> {
+    self.list_following(folder_id, opts, false)
+  }
+
+  /// The same as list, but a Recursive(true) listing also goes into the folders that shortcuts
+  /// point to when follow is true. Each folder is only listed once, so shortcuts to a parent
+  /// folder don't loop forever
+  fn list_following(
+    &self,
+    folder_id: &str,
+    opts: Vec<models::FileOpts>,
+    follow: bool,
+  ) -> Result<Vec<models::File>, DriveError> {
+    let recursive = opts.contains(&models::FileOpts::Recursive(true));
+
+    let mut files = vec![];
+    let mut folders = vec![folder_id.to_string()];
+    let mut listed: HashSet<String> = folders.iter().cloned().collect();
+    while let Some(folder_id) = folders.pop() {
+      let request = models::FileRequest {
+        parent_id: Some(folder_id),
+        filters: vec![],
+        opts: opts.clone(),
+        page_token: None,
+        drive_id: None,
+      };
+      for file in FilePages::new(&self.api, request) {
+        let file = file?;
+        let subfolder = match &file.shortcut_details {
+          Some(details) if follow && details.target_mime_type == Some(models::MimeType::Folder) => {
+            Some(details.target_id.clone())
+          }
+          _ if file.is_folder() => file.id.clone(),
+          _ => None,
+        };
+        if let Some(id) = subfolder {
+          if recursive && listed.insert(id.clone()) {
+            folders.push(id);
+          }
+        }
+        files.push(file);
+      }
+    }
+    Ok(files)
+  }
+
+  /// List the files and folders contained in the directory at path
+  ///
+  /// Recursive(true) will also list the contents of every sub-folder, and IncludeTrashed(true) will
+  /// add any trashed children to the results
+  pub fn ls(
+    &self,
+    path: &str,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<models::FileResult, DriveError> {
+    let pwd = self.get_path_id(path)?;
+    debug!("Listing the contents of '{}' ({})", path, pwd);
+    Ok(models::FileResult {
+      files: self.list_following(&pwd, opts, self.follow_shortcuts)?,
+      next_page_token: None,
+    })
+  }
+
+  /// Search work_dir for files matching all of the filters, fetching every page of results
+  ///
+  /// With IsUnique(true) anything other than exactly one match is an error: NoMatch when nothing
+  /// matched and Ambiguous when several did. Trashed files are left out unless IncludeTrashed(true)
+  /// is given.
+  pub fn find(
+    &self,
+    work_dir: &str,
+    filters: Vec<models::FileFilter>,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<Box<models::FileResult>, DriveError> {
+    let unique = opts.contains(&models::FileOpts::IsUnique(true));
+    let query = filters
+      .iter()
+      .map(|filter| filter.to_string())
+      .collect::<Result<Vec<_>, _>>()?
+      .join(" and ");
+    let files = self
+      .find_iter(work_dir, filters, opts)?
+      .collect::<Result<Vec<_>, _>>()?;
+    if unique {
+      match files.len() {
+        0 => return Err(DriveError::NoMatch(format!("{} under {}", query, work_dir))),
+        1 => (),
+        _ => {
+          return Err(DriveError::Ambiguous {
+            path: work_dir.to_string(),
+            ids: files.iter().filter_map(|file| file.id.clone()).collect(),
+          })
+        }
+      }
+    }
+    Ok(Box::new(models::FileResult {
+      files,
+      next_page_token: None,
+    }))
+  }
+
+  /// Find the one file matching the filters, failing with NoMatch or Ambiguous otherwise
+  pub fn find_one(
+    &self,
+    work_dir: &str,
+    filters: Vec<models::FileFilter>,
+    mut opts: Vec<models::FileOpts>,
+  ) -> Result<models::File, DriveError> {
+    opts.retain(|opt| !matches!(opt, models::FileOpts::IsUnique(_)));
+    opts.push(models::FileOpts::IsUnique(true));
+    let mut result = self.find(work_dir, filters, opts)?;
+    Ok(result.files.remove(0))
+  }
+
+  /// The same search as find, but only fetches each page of results as the iterator reaches it
+  ///
+  /// This makes it cheap to stop early when searching a huge drive. Recursive(true) searches every
+  /// folder below work_dir, a chunk of folders per query.
+  pub fn find_iter(
+    &self,
+    work_dir: &str,
+    filters: Vec<models::FileFilter>,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<FilePages<'_>, DriveError> {
+    // Catch filters Drive can't run before any requests are made
+    for filter in &filters {
+      filter.to_string()?;
+    }
+    let parent_id = self.get_path_id(work_dir)?;
+    debug!("parent_id:\n{:#?}", parent_id);
+
+    if opts.contains(&models::FileOpts::Recursive(true)) {
+      let folders = self.subtree_ids(&parent_id)?;
+      debug!("Searching {} folders under '{}'", folders.len(), work_dir);
+      return Ok(FilePages::from_requests(
+        &self.api,
+        parent_requests(&folders, filters, opts),
+      ));
+    }
+
+    let request = models::FileRequest {
+      parent_id: Some(parent_id),
+      filters: filters,
+      opts: opts,
+      page_token: None,
+      drive_id: None,
+    };
+    Ok(FilePages::new(&self.api, request))
+  }
+
+  /// The IDs of folder_id and every folder below it
+  ///
+  /// This comes from the cached graph when it has the folder. Otherwise Drive is searched a level at
+  /// a time.
+  fn subtree_ids(&self, folder_id: &str) -> Result<Vec<String>, DriveError> {
+    let mut ids = vec![folder_id.to_string()];
+    let mut seen: HashSet<String> = ids.iter().cloned().collect();
+
+    if self.cache.graph_cache.contains_key(folder_id) {
+      let mut i = 0;
+      while i < ids.len() {
+        if let Some(node) = self.cache.graph_cache.get(&ids[i]) {
+          for child in &node.children {
+            if seen.insert(child.clone()) {
+              ids.push(child.clone());
+            }
+          }
+        }
+        i += 1;
+      }
+      return Ok(ids);
+    }
+
+    let mut level = ids.clone();
+    while !level.is_empty() {
+      let requests = parent_requests(
+        &level,
+        vec![models::FileFilter::Type(models::MimeType::Folder)],
+        vec![],
+      );
+      level = vec![];
+      for folder in FilePages::from_requests(&self.api, requests) {
+        if let Some(id) = folder?.id {
+          if seen.insert(id.clone()) {
+            level.push(id);
+          }
+        }
+      }
+      ids.extend(level.clone());
+    }
+    Ok(ids)
+  }
+
+  /// Look up the folders named name directly inside of the parent folder
+  fn find_folders(&self, parent_id: &str, name: &str) -> Result<Vec<models::File>, DriveError> {
+    let request = models::FileRequest {
+      parent_id: Some(parent_id.to_string()),
+      filters: vec![
+        models::FileFilter::Name(models::Filter::Equals(name.to_string())),
+        models::FileFilter::Type(models::MimeType::Folder),
+      ],
+      opts: vec![],
+      page_token: None,
+      drive_id: None,
+    };
+    FilePages::new(&self.api, request).collect()
+  }
+
+  /// Create a folder named name inside of parent_id and add it to the cache
+  fn create_folder(&mut self, parent_id: &str, name: &str) -> Result<models::File, DriveError> {
+    let request = models::CreateFolder {
+      mime_type: models::MimeType::Folder,
+      name: name.to_string(),
+      parents: vec![parent_id.to_string()],
+    };
+    let created: Box<models::File> = self.api.call("create", request)?;
+    self.cache_folder(&created, parent_id)?;
+    Ok(*created)
+  }
+
+  /// Add a folder found or created inside of parent_id to the cache. A cache that was never loaded
+  /// only knows the root by its "root" alias, so the real ID is picked up from the folder's parents
+  fn cache_folder(&mut self, folder: &models::File, parent_id: &str) -> Result<(), DriveError> {
+    if parent_id == "root" {
+      if let Some(root_id) = folder.parents.as_ref().and_then(|parents| parents.first()) {
+        self.cache.set_root_id(root_id);
+      }
+    }
+    self.cache.add_folder(folder)
+  }
+
+  /// Create a folder at the given path
+  ///
+  /// Parents(true) acts like `mkdir -p`, creating any missing parent folders and not treating an
+  /// existing folder as an error. New folders are added to the cache as they are created.
+  pub fn mkdir(
+    &mut self,
+    path: &str,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<models::File, DriveError> {
+    let make_parents = opts.contains(&models::FileOpts::Parents(true));
+    let (root_id, root_path, rest) = self.path_root(path)?;
+    let names: Vec<&str> = match rest.trim_matches('/') {
+      "" => Err(DriveError::Invalid(format!(
+        "mkdir: cannot create directory '{}': It is the root",
+        path
+      )))?,
+      trimmed => trimmed.split('/').collect(),
+    };
+    if names.contains(&"") {
+      Err(DriveError::Invalid(format!(
+        "mkdir: found a double '/' in the path '{}'",
+        path
+      )))?;
+    }
+
+    let mut parent_id = root_id;
+    let mut folder = None;
+    for (i, name) in names.iter().enumerate() {
+      let is_last = i == names.len() - 1;
+      let cwd = join_path(&root_path, &names[..=i].join("/"));
+
+      let existing = self.child_folder(&parent_id, name, &cwd)?;
+      if let Some(found) = &existing {
+        if let Some(id) = &found.id {
+          if !self.cache.graph_cache.contains_key(id) {
+            self.cache_folder(found, &parent_id)?;
+          }
+        }
+      }
+
+      let current = match existing {
+        Some(_) if is_last && !make_parents => Err(DriveError::AlreadyExists(path.to_string()))?,
+        Some(existing) => existing,
+        None if !is_last && !make_parents => Err(DriveError::Invalid(format!(
+          "mkdir: cannot create directory '{}': '{}' does not exist",
+          path, cwd
+        )))?,
+        None => {
+          debug!("Creating the folder '{}'", cwd);
+          self.create_folder(&parent_id, name)?
+        }
+      };
+
+      parent_id = current.id.clone().ok_or_else(|| missing_id("folder"))?;
+      folder = Some(current);
+    }
+
+    Ok(folder.unwrap())
+  }
+
+  /// Make sure a folder is empty before removing it, unless the removal is Recursive(true)
+  fn check_removable(
+    &self,
+    target: &models::FileRef,
+    file: &models::File,
+    opts: &[models::FileOpts],
+  ) -> Result<(), DriveError> {
+    if !file.is_folder() || opts.contains(&models::FileOpts::Recursive(true)) {
+      return Ok(());
+    }
+    let id = file.id.clone().ok_or_else(|| missing_id("folder"))?;
+    // The contents of a trashed folder are trashed too, so they'd be skipped by default
+    let list_opts = vec![models::FileOpts::IncludeTrashed(file.trashed == Some(true))];
+    match self.list(&id, list_opts)?.is_empty() {
+      true => Ok(()),
+      false => Err(DriveError::Invalid(format!(
+        "rm: cannot remove {:?}: Folder is not empty. Use Recursive(true) to remove it",
+        target
+      )))?,
+    }
+  }
+
+  fn set_trashed(
+    &mut self,
+    file: &models::File,
+    trashed: bool,
+  ) -> Result<models::File, DriveError> {
+    let request = models::UpdateRequest {
+      file_id: file.id.clone().ok_or_else(|| missing_id("file"))?,
+      update: models::FileUpdate {
+        trashed: Some(trashed),
+        ..Default::default()
+      },
+      add_parents: vec![],
+      remove_parents: vec![],
+    };
+    let result: Box<models::File> = self.api.call("update", request)?;
+    Ok(*result)
+  }
+
+  /// Move a file or folder to the trash
+  ///
+  /// Folders that still have contents need Recursive(true), which trashes everything inside them.
+  pub fn rm(
+    &mut self,
+    target: models::FileRef,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<models::File, DriveError> {
+    let file = self.resolve(&target, vec![])?;
+    self.check_removable(&target, &file, &opts)?;
+
+    debug!("Moving {:?} to the trash", target);
+    let trashed = self.set_trashed(&file, true)?;
+    if trashed.is_folder() {
+      let id = trashed.id.as_ref().ok_or_else(|| missing_id("folder"))?;
+      self.cache.remove_folder(id)?;
+    }
+    Ok(trashed)
+  }
+
+  /// Take a file or folder back out of the trash
+  ///
+  /// Restoring a folder also restores its contents, so any sub-folders are added back to the cache.
+  pub fn restore(&mut self, target: models::FileRef) -> Result<models::File, DriveError> {
+    let file = self.resolve(&target, vec![models::FileOpts::IncludeTrashed(true)])?;
+    if file.trashed == Some(false) {
+      Err(DriveError::Invalid(format!(
+        "restore: {:?} is not in the trash",
+        target
+      )))?;
+    }
+
+    debug!("Restoring {:?} from the trash", target);
+    let restored = self.set_trashed(&file, false)?;
+    if restored.is_folder() {
+      self.cache.add_folder(&restored)?;
+      let contents = self.list(
+        restored.id.as_ref().ok_or_else(|| missing_id("folder"))?,
+        vec![models::FileOpts::Recursive(true)],
+      )?;
+      for folder in contents.iter().filter(|file| file.is_folder()) {
+        self.cache.add_folder(folder)?;
+      }
+    }
+    Ok(restored)
+  }
+
+  /// Delete a file or folder forever, skipping the trash. This cannot be undone.
+  ///
+  /// Folders that still have contents need Recursive(true), which deletes everything inside them.
+  pub fn delete_permanently(
+    &mut self,
+    target: models::FileRef,
+    opts: Vec<models::FileOpts>,
+  ) -> Result<(), DriveError> {
+    let file = self.resolve(&target, vec![models::FileOpts::IncludeTrashed(true)])?;
+    self.check_removable(&target, &file, &opts)?;
+
+    debug!("Permanently deleting {:?}", target);
+    let id = file.id.clone().ok_or_else(|| missing_id("file"))?;
+    let _result: Box<models::EmptyResult> = self.api.call(
+      "delete",
+      models::DeleteRequest {
+        file_id: id.clone(),
+      },
+    )?;
+    if file.is_folder() {
+      self.cache.remove_folder(&id)?;
+    }
+    Ok(())
+  }
+
+  /// Work out where a shell style command like `mv` or `cp` should put the file
+  ///
+  /// Returns the ID of the destination folder, the name to use and the full destination path. If
+  /// dst is an existing folder, the file keeps its name and goes inside of it. Otherwise the file
+  /// takes the last part of dst as its name. Existing files are never overwritten, except by the
+  /// file itself when it matches keep_id.
+  fn destination(
+    &self,
+    cmd: &str,
+    src: &str,
+    dst: &str,
+    file: &models::File,
+    keep_id: Option<&String>,
+  ) -> Result<(String, String, String), DriveError> {
+    let file_id = file.id.clone().ok_or_else(|| missing_id("file"))?;
+    let src_name = file.name.clone().unwrap_or_default();
+
+    let (dst_dir, dst_name) = split_path(dst);
+    let (parent_id, name, dst_path) = match dst_name {
+      "" => (
+        self.get_path_id(dst_dir)?,
+        src_name.clone(),
+        join_path(dst_dir, &src_name),
+      ),
+      _ => {
+        let dir_id = self.get_path_id(dst_dir)?;
+        let mut existing = self.find_named(&dir_id, dst_name, vec![])?;
+        match existing.len() {
+          0 => (dir_id, dst_name.to_string(), dst.to_string()),
+          1 if existing[0].is_folder() => (
+            existing.remove(0).id.ok_or_else(|| missing_id("folder"))?,
+            src_name.clone(),
+            format!("{}/{}", dst.trim_end_matches('/'), src_name),
+          ),
+          1 => Err(DriveError::AlreadyExists(dst.to_string()))?,
+          _ => Err(ambiguous_path(
+            dst,
+            &existing
+              .into_iter()
+              .filter_map(|file| file.id)
+              .collect::<Vec<_>>(),
+          ))?,
+        }
+      }
+    };
+
+    if file.is_folder() && self.cache.is_ancestor(&file_id, &parent_id) {
+      Err(DriveError::Invalid(format!(
+        "{}: cannot {} '{}' to a subdirectory of itself, '{}'",
+        cmd, cmd, src, dst
+      )))?;
+    }
+    let conflicts = self.find_named(&parent_id, &name, vec![])?;
+    if conflicts.iter().any(|x| x.id.as_ref() != keep_id) {
+      Err(DriveError::AlreadyExists(dst_path.clone()))?;
+    }
+    Ok((parent_id, name, dst_path))
+  }
+
+  /// Move and/or rename a file, like the shell's `mv`
+  ///
+  /// If dst is an existing folder, src is moved into it keeping its name. Otherwise src is moved
+  /// into dst's parent folder and renamed to the last part of dst. Existing files are never
+  /// overwritten.
+  pub fn mv(&mut self, src: &str, dst: &str) -> Result<models::File, DriveError> {
+    let file = self.get_file(src, vec![])?;
+    let (parent_id, name, _) = self.destination("mv", src, dst, &file, file.id.as_ref())?;
+
+    let current_parents = file.parents.clone().unwrap_or_default();
+    let request = models::UpdateRequest {
+      file_id: file.id.clone().ok_or_else(|| missing_id("file"))?,
+      update: models::FileUpdate {
+        name: match Some(&name) == file.name.as_ref() {
+          true => None,
+          false => Some(name),
+        },
+        ..Default::default()
+      },
+      add_parents: match current_parents.contains(&parent_id) {
+        true => vec![],
+        false => vec![parent_id.clone()],
+      },
+      remove_parents: current_parents
+        .into_iter()
+        .filter(|x| x != &parent_id)
+        .collect(),
+    };
+    debug!("Moving '{}' to '{}'", src, dst);
+    let moved: Box<models::File> = self.api.call("update", request)?;
+    if moved.is_folder() {
+      self.cache.move_folder(&moved)?;
+    }
+    Ok(*moved)
+  }
+
+  /// Copy a single file into the folder parent_id
+  fn copy_file(
+    &self,
+    file: &models::File,
+    parent_id: &str,
+    name: &str,
+  ) -> Result<models::File, DriveError> {
+    let request = models::CopyRequest {
+      file_id: file.id.clone().ok_or_else(|| missing_id("file"))?,
+      name: name.to_string(),
+      parents: vec![parent_id.to_string()],
+    };
+    let result: Box<models::File> = self.api.call("copy", request)?;
+    Ok(*result)
+  }
+
+  /// The (id, name) of the folders inside of folder_id, taken from the cache when it is loaded
+  fn subfolders(&self, folder_id: &str, contents: &[models::File]) -> Vec<(String, String)> {
+    match self.cache.graph_cache.get(folder_id) {
+      Some(node) => node
+        .children
+        .iter()
+        .filter_map(|child| self.cache.graph_cache.get(child))
+        .map(|child| (child.id.clone(), child.name.clone()))
+        .collect(),
+      None => contents
+        .iter()
+        .filter(|file| file.is_folder())
+        .filter_map(|file| Some((file.id.clone()?, file.name.clone()?)))
+        .collect(),
+    }
+  }
+
+  /// Copy a file, or a whole folder tree when recursive, like the shell's `cp`
+  ///
+  /// Drive can only copy single files, so folders are recreated at the destination and each file
+  /// inside is copied one at a time. Every item gets a CopyResult, so a failure part way through
+  /// doesn't hide what was already copied. The destination follows the same rules as `mv`.
+  pub fn cp(
+    &mut self,
+    src: &str,
+    dst: &str,
+    recursive: bool,
+  ) -> Result<Vec<models::CopyResult>, DriveError> {
+    let file = self.get_file(src, vec![])?;
+    let (parent_id, name, dst_path) = self.destination("cp", src, dst, &file, None)?;
+
+    if !file.is_folder() {
+      debug!("Copying '{}' to '{}'", src, dst_path);
+      return Ok(vec![models::CopyResult {
+        source: src.to_string(),
+        destination: dst_path,
+        result: self.copy_file(&file, &parent_id, &name),
+      }]);
+    }
+    if !recursive {
+      Err(DriveError::Invalid(format!(
+        "cp: '{}' is a folder. Set recursive to copy it",
+        src
+      )))?;
+    }
+
+    let mut results = vec![];
+    let mut folders = vec![(
+      file.id.clone().ok_or_else(|| missing_id("folder"))?,
+      src.trim_end_matches('/').to_string(),
+      parent_id,
+      name,
+      dst_path,
+    )];
+    while let Some((src_id, src_path, parent_id, name, dst_path)) = folders.pop() {
+      debug!("Copying the folder '{}' to '{}'", src_path, dst_path);
+      let created = self.create_folder(&parent_id, &name);
+      let new_id = match &created {
+        Ok(folder) => folder.id.clone(),
+        Err(_) => None,
+      };
+      results.push(models::CopyResult {
+        source: src_path.clone(),
+        destination: dst_path.clone(),
+        result: created,
+      });
+      // Nothing can be copied into a folder that couldn't be made
+      let new_id = match new_id {
+        Some(id) => id,
+        None => continue,
+      };
+
+      let contents = match self.list(&src_id, vec![]) {
+        Ok(contents) => contents,
+        Err(err) => {
+          results.push(models::CopyResult {
+            source: src_path,
+            destination: dst_path,
+            result: Err(err),
+          });
+          continue;
+        }
+      };
+      for item in contents.iter().filter(|file| !file.is_folder()) {
+        let item_name = item.name.clone().unwrap_or_default();
+        results.push(models::CopyResult {
+          source: format!("{}/{}", src_path, item_name),
+          destination: format!("{}/{}", dst_path, item_name),
+          result: self.copy_file(item, &new_id, &item_name),
+        });
+      }
+      for (child_id, child_name) in self.subfolders(&src_id, &contents) {
+        folders.push((
+          child_id,
+          format!("{}/{}", src_path, child_name),
+          new_id.clone(),
+          child_name.clone(),
+          format!("{}/{}", dst_path, child_name),
+        ));
+      }
+    }
+    Ok(results)
+  }
+
+  /// Work out the name, parent and type of a file being uploaded to drive_path
+  fn upload_metadata(
+    &self,
+    drive_path: &str,
+    local_name: Option<String>,
+    opts: &[models::UploadOpts],
+  ) -> Result<models::CreateFile, DriveError> {
+    let (dir, name) = split_path(drive_path);
+    let dir_id = self.get_path_id(dir)?;
+    let (parent_id, name) = match name {
+      "" => (dir_id, None),
+      _ => {
+        let mut existing = self.find_named(&dir_id, name, vec![])?;
+        match existing.len() {
+          0 => (dir_id, Some(name.to_string())),
+          1 if existing[0].is_folder() => (
+            existing.remove(0).id.ok_or_else(|| missing_id("folder"))?,
+            None,
+          ),
+          1 => Err(DriveError::AlreadyExists(drive_path.to_string()))?,
+          _ => Err(ambiguous_path(
+            drive_path,
+            &existing
+              .into_iter()
+              .filter_map(|file| file.id)
+              .collect::<Vec<_>>(),
+          ))?,
+        }
+      }
+    };
+
+    // Uploading into a folder keeps the local name, as long as that isn't taken too
+    let name = match name {
+      Some(name) => name,
+      None => {
+        let name = local_name.ok_or_else(|| {
+          DriveError::Invalid(format!(
+            "put: '{}' is a folder and the upload has no file name to use",
+            drive_path
+          ))
+        })?;
+        if !self.find_named(&parent_id, &name, vec![])?.is_empty() {
+          Err(DriveError::AlreadyExists(join_path(drive_path, &name)))?;
+        }
+        name
+      }
+    };
+
+    let mime_type = opts
+      .iter()
+      .find_map(|opt| match opt {
+        models::UploadOpts::MimeType(mime_type) => Some(mime_type.clone()),
+        _ => None,
+      })
+      .or_else(|| {
+        std::path::Path::new(&name)
+          .extension()
+          .and_then(|ext| models::MimeType::from_extension(&ext.to_string_lossy()))
+      });
+
+    Ok(models::CreateFile {
+      mime_type,
+      name,
+      parents: vec![parent_id],
+    })
+  }
+
+  /// Ask a resumable upload session how much of the content it has received
+  fn upload_status(
+    &self,
+    session: &models::UploadSession,
+    total: Option<u64>,
+  ) -> Result<models::UploadStatus, DriveError> {
+    let request = models::UploadChunkRequest {
+      session_uri: session.uri.clone(),
+      content: vec![],
+      offset: 0,
+      total,
+    };
+    let status = self
+      .api
+      .retry
+      .run("upload_status", true, || self.api.send_chunk(&request))?;
+    Ok(status)
+  }
+
+  /// Send the rest of the content to a resumable upload session, starting at offset
+  ///
+  /// Chunks are sent once each, outside of the RetryPolicy. After a failure this waits as long as
+  /// the policy would, asks the session what it received and sends only the rest. A failed status
+  /// query counts as a failure too, and is asked again before anything is resent.
+  fn send_chunks(
+    &self,
+    session: &models::UploadSession,
+    reader: &mut dyn Read,
+    mut offset: u64,
+    chunk_size: usize,
+  ) -> Result<models::File, DriveError> {
+    loop {
+      let chunk = read_chunk(reader, chunk_size)?;
+      let is_last = chunk.len() < chunk_size;
+      let total = match is_last {
+        true => Some(offset + chunk.len() as u64),
+        false => session.size,
+      };
+
+      let mut sent = 0;
+      let mut failures = 0;
+      // After a failure, Drive has to say what it received before anything more is sent
+      let mut check_status = false;
+      loop {
+        let request = models::UploadChunkRequest {
+          session_uri: session.uri.clone(),
+          content: match check_status {
+            true => vec![],
+            false => chunk[sent..].to_vec(),
+          },
+          offset: offset + sent as u64,
+          total,
+        };
+        let status = match self.api.send_chunk(&request) {
+          Ok(status) => status,
+          Err(err) => {
+            failures += 1;
+            if failures > MAX_UPLOAD_RETRIES {
+              warn!(
+                "Upload session '{}' stopped at byte {} after {} failures",
+                session.uri,
+                offset + sent as u64,
+                failures
+              );
+              return Err(DriveError::UploadInterrupted {
+                session: models::UploadSession {
+                  uri: session.uri.clone(),
+                  size: total,
+                },
+                error: Box::new(err.into()),
+              });
+            }
+            let wait = self.api.retry.delay(failures as u32, err.retry_after());
+            warn!(
+              "Upload chunk failed, checking what Drive received in {:?}: {:?}",
+              wait, err
+            );
+            std::thread::sleep(wait);
+            check_status = true;
+            continue;
+          }
+        };
+        check_status = false;
+        match status {
+          models::UploadStatus::Complete(file) => return Ok(*file),
+          models::UploadStatus::Incomplete(received) => {
+            if received >= offset + chunk.len() as u64 {
+              break;
+            }
+            // Only resend what didn't make it
+            sent = received.saturating_sub(offset) as usize;
+          }
+        }
+      }
+
+      if is_last {
+        Err(DriveError::Invalid(format!(
+          "Upload session '{}' received all {} bytes but did not create the file",
+          session.uri,
+          offset + chunk.len() as u64
+        )))?;
+      }
+      offset += chunk.len() as u64;
+    }
+  }
+
+  /// Upload local content to a new file at drive_path
+  ///
+  /// If drive_path is an existing folder, the upload goes inside of it with the local file name.
+  /// Existing files are never overwritten. The MIME type is guessed from the file extension unless
+  /// it is set with UploadOpts::MimeType.
+  ///
+  /// By default, content up to 5MB is sent in one Multipart request and anything larger uses a
+  /// Resumable session. When a chunk fails, the session is asked how much it received and the rest
+  /// is sent again. If it keeps failing, the error is UploadInterrupted with the session to pass to
+  /// resume_upload.
+  pub fn put(
+    &self,
+    source: models::UploadSource,
+    drive_path: &str,
+    opts: Vec<models::UploadOpts>,
+  ) -> Result<models::File, DriveError> {
+    let OpenSource {
+      name,
+      size,
+      mut reader,
+    } = open_source(source)?;
+    let metadata = self.upload_metadata(drive_path, name, &opts)?;
+    let method = opts.iter().find_map(|opt| match opt {
+      models::UploadOpts::Method(method) => Some(method.clone()),
+      _ => None,
+    });
+
+    // Without a known size, read just past the multipart limit to see which side of it we're on
+    let mut head = vec![];
+    let method = match (method, size) {
+      (Some(method), _) => method,
+      (None, Some(size)) if size as usize <= models::MAX_MULTIPART_SIZE => {
+        models::UploadType::Multipart
+      }
+      (None, Some(_)) => models::UploadType::Resumable,
+      (None, None) => {
+        head = read_chunk(&mut reader, models::MAX_MULTIPART_SIZE + 1)?;
+        match head.len() <= models::MAX_MULTIPART_SIZE {
+          true => models::UploadType::Multipart,
+          false => models::UploadType::Resumable,
+        }
+      }
+    };
+
+    self.put_content(
+      metadata,
+      size,
+      method,
+      std::io::Cursor::new(head).chain(reader),
+      chunk_size(&opts),
+    )
+  }
+
+  fn put_content(
+    &self,
+    metadata: models::CreateFile,
+    size: Option<u64>,
+    method: models::UploadType,
+    mut reader: impl Read,
+    chunk_size: usize,
+  ) -> Result<models::File, DriveError> {
+    debug!("Uploading '{}' as {:?}", metadata.name, method);
+    let read_all = |reader: &mut dyn Read| -> Result<Vec<u8>, DriveError> {
+      let content = read_chunk(reader, models::MAX_MULTIPART_SIZE + 1)?;
+      match content.len() > models::MAX_MULTIPART_SIZE {
+        true => Err(DriveError::Invalid(format!(
+          "put: {:?} uploads are limited to {} bytes. Use UploadType::Resumable",
+          method,
+          models::MAX_MULTIPART_SIZE
+        )))?,
+        false => Ok(content),
+      }
+    };
+
+    match method {
+      models::UploadType::Multipart => {
+        let request = models::MultipartUploadRequest {
+          metadata,
+          content: read_all(&mut reader)?,
+        };
+        let result: Box<models::File> = self.api.call("upload", request)?;
+        Ok(*result)
+      }
+      models::UploadType::Resumable => {
+        let request = models::StartUploadRequest { metadata, size };
+        let mut session: Box<models::UploadSession> = self.api.call("upload", request)?;
+        session.size = size;
+        debug!("Started upload session '{}'", session.uri);
+        self.send_chunks(&session, &mut reader, 0, chunk_size)
+      }
+    }
+  }
+
+  /// Continue a resumable upload that failed part way through
+  ///
+  /// The source has to be the same content as the original upload. Whatever Drive already received
+  /// is skipped and the rest is sent.
+  pub fn resume_upload(
+    &self,
+    session: &models::UploadSession,
+    source: models::UploadSource,
+    opts: Vec<models::UploadOpts>,
+  ) -> Result<models::File, DriveError> {
+    let mut reader = open_source(source)?.reader;
+    let offset = match self.upload_status(session, session.size)? {
+      models::UploadStatus::Complete(file) => return Ok(*file),
+      models::UploadStatus::Incomplete(received) => received,
+    };
+
+    debug!(
+      "Resuming upload session '{}' from byte {}",
+      session.uri, offset
+    );
+    let skipped = std::io::copy(&mut (&mut reader).take(offset), &mut std::io::sink())
+      .map_err(|err| DriveError::Io(format!("Error skipping the uploaded content: {}", err)))?;
+    if skipped < offset {
+      Err(DriveError::Invalid(format!(
+        "resume_upload: Drive has {} bytes but the source only has {}",
+        offset, skipped
+      )))?;
+    }
+    self.send_chunks(session, &mut reader, offset, chunk_size(&opts))
+  }
+
+  /// Download the content of the file at drive_path into writer
+  ///
+  /// Large files are fetched in chunks so only one chunk is held in memory at a time. When Drive
+  /// has an md5Checksum for the file, the downloaded content is checked against it. Google Docs,
+  /// Sheets and other native types have no content to download and need to be exported instead.
+  pub fn get(&self, drive_path: &str, mut writer: impl Write) -> Result<models::File, DriveError> {
+    let file = self.downloadable(drive_path)?;
+    self.download_file(&file, drive_path, &mut writer)?;
+    Ok(file)
+  }
+
+  /// Look up the file at drive_path to download, failing for types that have to be exported
+  fn downloadable(&self, drive_path: &str) -> Result<models::File, DriveError> {
+    let file = self.follow(self.get_file(drive_path, vec![])?)?;
+    if let Some(mime_type) = &file.mime_type {
+      if mime_type.is_google_type() {
+        Err(DriveError::Unsupported(format!(
+          "get: '{}' is a {:?}, which has to be exported instead",
+          drive_path, mime_type
+        )))?;
+      }
+    }
+    Ok(file)
+  }
+
+  /// Download the content of file, which was found at drive_path, into writer
+  fn download_file(
+    &self,
+    file: &models::File,
+    drive_path: &str,
+    writer: &mut impl Write,
+  ) -> Result<(), DriveError> {
+    let request = models::DownloadRequest {
+      file_id: file.id.clone().ok_or_else(|| missing_id("file"))?,
+      revision_id: None,
+      range: None,
+    };
+    self.download(
+      "download",
+      request,
+      &file.size,
+      &file.md5_checksum,
+      writer,
+      &format!("get: '{}'", drive_path),
+    )
+  }
+
+  /// Download content in chunks into writer, checking it against md5_checksum when there is one
+  ///
+  /// Each chunk is a range of request. Without a size, the whole content is fetched at once. what
+  /// names the download in errors.
+  fn download(
+    &self,
+    endpoint: &str,
+    request: models::DownloadRequest,
+    size: &Option<String>,
+    md5_checksum: &Option<String>,
+    writer: &mut impl Write,
+    what: &str,
+  ) -> Result<(), DriveError> {
+    let ranges = match size.as_ref().and_then(|size| size.parse::<u64>().ok()) {
+      Some(0) => vec![],
+      Some(size) => (0..size)
+        .step_by(DOWNLOAD_CHUNK_SIZE as usize)
+        .map(|start| Some((start, std::cmp::min(start + DOWNLOAD_CHUNK_SIZE, size) - 1)))
+        .collect(),
+      None => vec![None],
+    };
+
+    debug!("Downloading {} in {} parts", what, ranges.len());
+    let write_error =
+      |err: std::io::Error| DriveError::Io(format!("{}: error writing: {}", what, err));
+    let mut checksum = md5::Context::new();
+    for range in ranges {
+      let request = models::DownloadRequest {
+        range,
+        ..request.clone()
+      };
+      let chunk: Box<models::MediaResult> = self.api.call(endpoint, request)?;
+      checksum.consume(&chunk.content);
+      writer.write_all(&chunk.content).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)?;
+
+    let digest = format!("{:x}", checksum.compute());
+    match md5_checksum {
+      Some(expected) if expected != &digest => Err(DriveError::Io(format!(
+        "{}: checksum mismatch. Expected {} but downloaded {}",
+        what, expected, digest
+      ))),
+      _ => Ok(()),
+    }
+  }
+
+  /// Download the content of the file at drive_path to a local file
+  ///
+  /// The content goes into `<local_path>.part` first, which is only moved over local_path once the
+  /// whole download has worked. A failed download leaves local_path as it was.
+  pub fn get_to_file(
+    &self,
+    drive_path: &str,
+    local_path: impl AsRef<std::path::Path>,
+  ) -> Result<models::File, DriveError> {
+    let local_path = local_path.as_ref();
+    let file = self.downloadable(drive_path)?;
+
+    let mut temp_path = local_path.as_os_str().to_owned();
+    temp_path.push(".part");
+    let temp_path = std::path::PathBuf::from(temp_path);
+    // Never truncate a file that happens to have the temporary name
+    let temp_file = std::fs::OpenOptions::new()
+      .write(true)
+      .create_new(true)
+      .open(&temp_path)
+      .map_err(|err| {
+        DriveError::Io(format!(
+          "Could not create '{}': {}",
+          temp_path.display(),
+          err
+        ))
+      })?;
+
+    let result = self.download_file(&file, drive_path, &mut std::io::BufWriter::new(temp_file));
+    if let Err(err) = result {
+      let _ = std::fs::remove_file(&temp_path);
+      return Err(err);
+    }
+    std::fs::rename(&temp_path, local_path).map_err(|err| {
+      let _ = std::fs::remove_file(&temp_path);
+      DriveError::Io(format!(
+        "Could not move the download to '{}': {}",
+        local_path.display(),
+        err
+      ))
+    })?;
+    Ok(file)
+  }
+
+  /// Convert a Google Doc, Sheet, Slides, Drawing or Apps Script to target and write it out
+  ///
+  /// The conversion is checked against MimeType::export_formats first, so an unsupported pair is
+  /// a DriveError::Unsupported instead of a failed call. Drive limits exports to 10MB.
+  pub fn export(
+    &self,
+    path: &str,
+    target: models::MimeType,
+    mut writer: impl Write,
+  ) -> Result<models::File, DriveError> {
+    let file = self.follow(self.get_file(path, vec![])?)?;
+    let allowed = match &file.mime_type {
+      Some(mime_type) => mime_type.export_formats(),
+      None => vec![],
+    };
+    if !allowed.contains(&target) {
+      return Err(unsupported_export(&file.mime_type, &target, &allowed));
+    }
+
+    debug!("Exporting '{}' as {:?}", path, target);
+    let request = models::ExportRequest {
+      file_id: file.id.clone().ok_or_else(|| missing_id("file"))?,
+      mime_type: target,
+    };
+    let result: Box<models::MediaResult> = self.api.call("export", request)?;
+    writer
+      .write_all(&result.content)
+      .and_then(|_| writer.flush())
+      .map_err(|err| DriveError::Io(format!("export: error writing '{}': {}", path, err)))?;
+    Ok(file)
+  }
+
+  /// The ID of the file at target, for calls that only need the ID
+  fn file_id(&self, target: &models::FileRef) -> Result<String, DriveError> {
+    match target {
+      models::FileRef::Id(id) => Ok(id.clone()),
+      models::FileRef::Path(_) => Ok(
+        self
+          .resolve(target, vec![])?
+          .id
+          .ok_or_else(|| missing_id("file"))?,
+      ),
+    }
+  }
+
+  /// List everyone a file or folder is shared with
+  pub fn permissions(
+    &self,
+    target: models::FileRef,
+  ) -> Result<Vec<models::Permission>, DriveError> {
+    let file_id = self.file_id(&target)?;
+    let mut permissions = vec![];
+    let mut page_token = None;
+    loop {
+      let page: Box<models::PermissionList> = self.api.call(
+        "permissions",
+        models::PermissionListRequest {
+          file_id: file_id.clone(),
+          page_token,
+        },
+      )?;
+      let page = *page;
+      permissions.extend(page.permissions);
+      match page.next_page_token {
+        Some(next) => page_token = Some(next),
+        None => return Ok(permissions),
+      }
+    }
+  }
+
+  /// Give grantee the role on a file or folder
+  ///
+  /// Users and groups get an email about it unless SendNotification(false) is given. Sharing with
+  /// Role::Owner transfers ownership, and the previous owner becomes a writer.
+  pub fn share(
+    &self,
+    target: models::FileRef,
+    grantee: models::Grantee,
+    role: models::Role,
+    opts: Vec<models::ShareOpts>,
+  ) -> Result<models::Permission, DriveError> {
+    debug!("Sharing {:?} with {:?} as {:?}", target, grantee, role);
+    let request = models::CreatePermissionRequest {
+      file_id: self.file_id(&target)?,
+      permission: models::Permission::new(grantee, role),
+      opts,
+    };
+    let permission: Box<models::Permission> = self.api.call("create_permission", request)?;
+    Ok(*permission)
+  }
+
+  /// Change the role of one of the permissions listed by permissions
+  pub fn update_permission(
+    &self,
+    target: models::FileRef,
+    permission_id: &str,
+    role: models::Role,
+  ) -> Result<models::Permission, DriveError> {
+    let request = models::UpdatePermissionRequest {
+      file_id: self.file_id(&target)?,
+      permission_id: permission_id.to_string(),
+      role,
+    };
+    let permission: Box<models::Permission> = self.api.call("update_permission", request)?;
+    Ok(*permission)
+  }
+
+  /// Remove one of the permissions listed by permissions, so the file is no longer shared that way
+  pub fn delete_permission(
+    &self,
+    target: models::FileRef,
+    permission_id: &str,
+  ) -> Result<(), DriveError> {
+    let request = models::DeletePermissionRequest {
+      file_id: self.file_id(&target)?,
+      permission_id: permission_id.to_string(),
+    };
+    let _result: Box<models::EmptyResult> = self.api.call("delete_permission", request)?;
+    Ok(())
+  }
+
+  /// List the saved versions of a file, oldest first, with when and by whom each was made
+  pub fn revisions(&self, target: models::FileRef) -> Result<Vec<models::Revision>, DriveError> {
+    let file_id = self.file_id(&target)?;
+    let mut revisions = vec![];
+    let mut page_token = None;
+    loop {
+      let page: Box<models::RevisionList> = self.api.call(
+        "revisions",
+        models::RevisionListRequest {
+          file_id: file_id.clone(),
+          page_token,
+        },
+      )?;
+      let page = *page;
+      revisions.extend(page.revisions);
+      match page.next_page_token {
+        Some(next) => page_token = Some(next),
+        None => return Ok(revisions),
+      }
+    }
+  }
+
+  fn get_revision(&self, file_id: &str, revision_id: &str) -> Result<models::Revision, DriveError> {
+    let request = models::RevisionRequest {
+      file_id: file_id.to_string(),
+      revision_id: revision_id.to_string(),
+    };
+    let revision: Box<models::Revision> = self.api.call("get_revision", request)?;
+    Ok(*revision)
+  }
+
+  /// Download the content of an old revision of a file into writer
+  ///
+  /// The content is checked against the revision's md5Checksum. Revisions of Google Docs, Sheets
+  /// and other native types need export_revision instead.
+  pub fn get_revision_content(
+    &self,
+    target: models::FileRef,
+    revision_id: &str,
+    mut writer: impl Write,
+  ) -> Result<models::Revision, DriveError> {
+    let file_id = self.file_id(&target)?;
+    let revision = self.get_revision(&file_id, revision_id)?;
+    if revision.export_links.is_some() {
+      Err(DriveError::Unsupported(format!(
+        "get_revision_content: revision {} of {:?} is a Google native file, which has to be exported instead",
+        revision_id, target
+      )))?;
+    }
+
+    let request = models::DownloadRequest {
+      file_id,
+      revision_id: Some(revision_id.to_string()),
+      range: None,
+    };
+    self.download(
+      "download_revision",
+      request,
+      &revision.size,
+      &revision.md5_checksum,
+      &mut writer,
+      &format!(
+        "get_revision_content: revision {} of {:?}",
+        revision_id, target
+      ),
+    )?;
+    Ok(revision)
+  }
+
+  /// Convert an old revision of a Google native file to target and write it out
+  ///
+  /// Each revision lists the formats it can be exported as, so anything else is an
+  /// DriveError::Unsupported.
+  pub fn export_revision(
+    &self,
+    target: models::FileRef,
+    revision_id: &str,
+    format: models::MimeType,
+    mut writer: impl Write,
+  ) -> Result<models::Revision, DriveError> {
+    let file_id = self.file_id(&target)?;
+    let revision = self.get_revision(&file_id, revision_id)?;
+    let links = revision.export_links.clone().unwrap_or_default();
+    let export_link = match links.get(&format.to_string()) {
+      Some(link) => link.clone(),
+      None => {
+        let allowed: Vec<models::MimeType> = links
+          .keys()
+          .filter_map(|mime_type| {
+            serde_json::from_value(serde_json::Value::String(mime_type.clone())).ok()
+          })
+          .collect();
+        return Err(unsupported_export(&revision.mime_type, &format, &allowed));
+      }
+    };
+
+    debug!(
+      "Exporting revision {} of {:?} as {:?}",
+      revision_id, target, format
+    );
+    let result: Box<models::MediaResult> = self.api.call(
+      "export_revision",
+      models::RevisionExportRequest { export_link },
+    )?;
+    writer
+      .write_all(&result.content)
+      .and_then(|_| writer.flush())
+      .map_err(|err| {
+        DriveError::Io(format!(
+          "export_revision: error writing {:?}: {}",
+          target, err
+        ))
+      })?;
+    Ok(revision)
+  }
+
+  /// Pin a revision so Drive never clears it out, or unpin it with keep_forever false
+  ///
+  /// Only files with binary content can pin revisions, and at most 200 per file.
+  pub fn keep_revision(
+    &self,
+    target: models::FileRef,
+    revision_id: &str,
+    keep_forever: bool,
+  ) -> Result<models::Revision, DriveError> {
+    let request = models::UpdateRevisionRequest {
+      file_id: self.file_id(&target)?,
+      revision_id: revision_id.to_string(),
+      keep_forever,
+    };
+    let revision: Box<models::Revision> = self.api.call("update_revision", request)?;
+    Ok(*revision)
+  }
+
+  /// Delete an old revision of a file with binary content
+  ///
+  /// Google native files and the latest revision of a file can't be deleted this way.
+  pub fn delete_revision(
+    &self,
+    target: models::FileRef,
+    revision_id: &str,
+  ) -> Result<(), DriveError> {
+    let request = models::DeleteRevisionRequest {
+      file_id: self.file_id(&target)?,
+      revision_id: revision_id.to_string(),
+    };
+    let _result: Box<models::EmptyResult> = self.api.call("delete_revision", request)?;
+    Ok(())
+  }
+
+  /// List the comments on a file, each with its replies
+  pub fn comments(&self, target: models::FileRef) -> Result<Vec<models::Comment>, DriveError> {
+    let file_id = self.file_id(&target)?;
+    let mut comments = vec![];
+    let mut page_token = None;
+    loop {
+      let page: Box<models::CommentList> = self.api.call(
+        "comments",
+        models::CommentListRequest {
+          file_id: file_id.clone(),
+          page_token,
+        },
+      )?;
+      let page = *page;
+      comments.extend(page.comments);
+      match page.next_page_token {
+        Some(next) => page_token = Some(next),
+        None => return Ok(comments),
+      }
+    }
+  }
+
+  /// Add a comment to a file, anchored to part of it when the comment has an anchor
+  pub fn comment(
+    &self,
+    target: models::FileRef,
+    comment: models::NewComment,
+  ) -> Result<models::Comment, DriveError> {
+    let request = models::CreateCommentRequest {
+      file_id: self.file_id(&target)?,
+      comment,
+    };
+    let comment: Box<models::Comment> = self.api.call("create_comment", request)?;
+    Ok(*comment)
+  }
+
+  /// Answer a comment, which can also resolve or reopen it
+  pub fn reply(
+    &self,
+    target: models::FileRef,
+    comment_id: &str,
+    content: &str,
+    action: Option<models::ReplyAction>,
+  ) -> Result<models::Reply, DriveError> {
+    let request = models::CreateReplyRequest {
+      file_id: self.file_id(&target)?,
+      comment_id: comment_id.to_string(),
+      content: content.to_string(),
+      action,
+    };
+    let reply: Box<models::Reply> = self.api.call("create_reply", request)?;
+    Ok(*reply)
+  }
+
+  /// Mark a comment as resolved. Drive does this with a reply, which is returned
+  pub fn resolve_comment(
+    &self,
+    target: models::FileRef,
+    comment_id: &str,
+  ) -> Result<models::Reply, DriveError> {
+    self.reply(target, comment_id, "", Some(models::ReplyAction::Resolve))
+  }
+
+  /// Make a shortcut at link_path pointing to target, like `ln -s`
+  pub fn ln(&self, target: models::FileRef, link_path: &str) -> Result<models::File, DriveError> {
+    let target_id = self.file_id(&target)?;
+    let (dir, name) = split_path(link_path);
+    if name.is_empty() {
+      Err(DriveError::Invalid(format!(
+        "ln: failed to create shortcut '{}': No name given",
+        link_path
+      )))?;
+    }
+    let parent_id = self.get_path_id(dir)?;
+    if !self.find_named(&parent_id, name, vec![])?.is_empty() {
+      Err(DriveError::AlreadyExists(link_path.to_string()))?;
+    }
+
+    debug!("Linking '{}' to {:?}", link_path, target);
+    let request = models::CreateShortcut {
+      mime_type: models::MimeType::Shortcut,
+      name: name.to_string(),
+      parents: vec![parent_id],
+      shortcut_details: models::ShortcutDetails {
+        target_id,
+        target_mime_type: None,
+      },
+    };
+    let shortcut: Box<models::File> = self.api.call("create", request)?;
+    Ok(*shortcut)
+  }
 }
@@ -1,69 +1,226 @@
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use wrapi::{WrapiError, WrapiRequest, WrapiResult};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-// #[serde(untagged)]
+/// The fields we request for a single file
+fn file_fields() -> String {
+  FieldMask::standard().to_string()
+}
+/// Without this, Drive acts as if shared drives don't exist
+const SUPPORTS_ALL_DRIVES: (&str, &str) = ("supportsAllDrives", "true");
+
+/// A MIME type Drive uses. Types without a variant of their own come through as Other
+#[derive(Clone, Debug, PartialEq)]
 pub enum MimeType {
-  #[serde(rename = "application/vnd.google-apps.audio")]
   Audio,
-  #[serde(rename = "text/csv")]
   CSV,
-  #[serde(rename = "application/vnd.google-apps.document")] //	Google Docs
   Doc,
-  #[serde(rename = "application/vnd.google-apps.drawing")] //	Google Drawing
   Drawing,
-  #[serde(rename = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")]
+  EPUB,
   Excel,
-  #[serde(rename = "application/vnd.google-apps.file")] //	Google Drive file
   File,
-  #[serde(rename = "application/vnd.google-apps.folder")] //	Google Drive folder
   Folder,
-  #[serde(rename = "application/vnd.google-apps.form")] //	Google Forms
   Form,
-  #[serde(rename = "application/vnd.google-apps.fusiontable")] //	Google Fusion Tables
   FusionTable,
-  #[serde(rename = "image/gif")]
   Gif,
-  #[serde(rename = "text/html")] //	Html Document
   HTML,
-  #[serde(rename = "application/vnd.google-apps.map")] //	Google My Maps
   Map,
-  #[serde(rename = "image/jpeg")]
   Jpeg,
-  #[serde(rename = "application/pdf")]
+  ODP,
+  ODS,
+  ODT,
   PDF,
-  #[serde(rename = "application/vnd.google-apps.photo")] //
   Photo,
-  #[serde(rename = "image/png")]
   PNG,
-  #[serde(rename = "application/vnd.google-apps.presentation")] //	Google Slides
+  PowerPoint,
   Presentation,
-  #[serde(rename = "application/vnd.google-apps.script")] //	Google Apps Scripts
+  RTF,
   Script,
-  #[serde(rename = "application/vnd.google-apps.drive-sdk")] //	3rd party shortcut
+  ScriptJson,
   Shortcut,
-  #[serde(rename = "application/vnd.google-apps.site")] //	Google Sites
   Site,
-  #[serde(rename = "application/vnd.google-apps.spreadsheet")] //	Google Sheets
   Spreadsheet,
-  #[serde(rename = "text/plain")]
+  SVG,
   Text,
-  #[serde(rename = "application/vnd.google-apps.unknown")]
+  ThirdPartyShortcut,
+  TSV,
   Unknown,
-  #[serde(rename = "application/vnd.google-apps.video")] //
   Video,
-  #[serde(rename = "application/vnd.openxmlformats-officedocument.wordprocessingml.document")] //
   Word,
-  #[serde(rename = "application/zip")]
   Zip,
+  Other(String),
 }
 
+/// The MIME type string for each of the named variants
+const MIME_TYPES: &[(MimeType, &str)] = &[
+  (MimeType::Audio, "application/vnd.google-apps.audio"),
+  (MimeType::CSV, "text/csv"),
+  (MimeType::Doc, "application/vnd.google-apps.document"), // Google Docs
+  (MimeType::Drawing, "application/vnd.google-apps.drawing"), // Google Drawing
+  (MimeType::EPUB, "application/epub+zip"),
+  (
+    MimeType::Excel,
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+  ),
+  (MimeType::File, "application/vnd.google-apps.file"), // Google Drive file
+  (MimeType::Folder, "application/vnd.google-apps.folder"), // Google Drive folder
+  (MimeType::Form, "application/vnd.google-apps.form"), // Google Forms
+  (
+    MimeType::FusionTable,
+    "application/vnd.google-apps.fusiontable",
+  ), // Google Fusion Tables
+  (MimeType::Gif, "image/gif"),
+  (MimeType::HTML, "text/html"),                      // Html Document
+  (MimeType::Map, "application/vnd.google-apps.map"), // Google My Maps
+  (MimeType::Jpeg, "image/jpeg"),
+  (
+    MimeType::ODP,
+    "application/vnd.oasis.opendocument.presentation",
+  ), // OpenDocument Presentation
+  (
+    MimeType::ODS,
+    "application/x-vnd.oasis.opendocument.spreadsheet",
+  ), // OpenDocument Spreadsheet
+  (MimeType::ODT, "application/vnd.oasis.opendocument.text"), // OpenDocument Text
+  (MimeType::PDF, "application/pdf"),
+  (MimeType::Photo, "application/vnd.google-apps.photo"),
+  (MimeType::PNG, "image/png"),
+  (
+    MimeType::PowerPoint,
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+  ),
+  (
+    MimeType::Presentation,
+    "application/vnd.google-apps.presentation",
+  ), // Google Slides
+  (MimeType::RTF, "application/rtf"),
+  (MimeType::Script, "application/vnd.google-apps.script"), // Google Apps Scripts
+  (
+    MimeType::ScriptJson,
+    "application/vnd.google-apps.script+json",
+  ), // Apps Scripts exported as JSON
+  (MimeType::Shortcut, "application/vnd.google-apps.shortcut"), // Drive shortcut
+  (MimeType::Site, "application/vnd.google-apps.site"),     // Google Sites
+  (
+    MimeType::Spreadsheet,
+    "application/vnd.google-apps.spreadsheet",
+  ), // Google Sheets
+  (MimeType::SVG, "image/svg+xml"),
+  (MimeType::Text, "text/plain"),
+  (
+    MimeType::ThirdPartyShortcut,
+    "application/vnd.google-apps.drive-sdk",
+  ), // 3rd party shortcut
+  (MimeType::TSV, "text/tab-separated-values"),
+  (MimeType::Unknown, "application/vnd.google-apps.unknown"),
+  (MimeType::Video, "application/vnd.google-apps.video"),
+  (
+    MimeType::Word,
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+  ),
+  (MimeType::Zip, "application/zip"),
+];
+
 impl MimeType {
   pub fn to_string(&self) -> String {
-    let mut value = serde_json::to_string(self).unwrap();
-    value.pop();
-    value.remove(0);
-    value
+    match self {
+      MimeType::Other(value) => value.clone(),
+      known => MIME_TYPES
+        .iter()
+        .find(|(mime_type, _)| mime_type == known)
+        .map(|(_, value)| value.to_string())
+        .unwrap_or_default(),
+    }
+  }
+
+  /// Check if this is one of Google's own formats, which have no content to download directly
+  pub fn is_google_type(&self) -> bool {
+    self.to_string().starts_with("application/vnd.google-apps.")
+  }
+
+  /// The formats a Google native file can be exported to. Anything else can't be exported
+  pub fn export_formats(&self) -> Vec<MimeType> {
+    match self {
+      MimeType::Doc => vec![
+        MimeType::Word,
+        MimeType::ODT,
+        MimeType::RTF,
+        MimeType::PDF,
+        MimeType::Text,
+        MimeType::HTML,
+        MimeType::Zip,
+        MimeType::EPUB,
+      ],
+      MimeType::Spreadsheet => vec![
+        MimeType::Excel,
+        MimeType::ODS,
+        MimeType::PDF,
+        MimeType::CSV,
+        MimeType::TSV,
+        MimeType::Zip,
+      ],
+      MimeType::Presentation => vec![
+        MimeType::PowerPoint,
+        MimeType::ODP,
+        MimeType::PDF,
+        MimeType::Text,
+        MimeType::Jpeg,
+        MimeType::PNG,
+        MimeType::SVG,
+      ],
+      MimeType::Drawing => vec![MimeType::PDF, MimeType::Jpeg, MimeType::PNG, MimeType::SVG],
+      MimeType::Script => vec![MimeType::ScriptJson],
+      _ => vec![],
+    }
+  }
+
+  /// Guess the type of a local file from its extension, leaving anything unknown for Drive to detect
+  pub fn from_extension(ext: &str) -> Option<MimeType> {
+    match &ext.to_lowercase()[..] {
+      "csv" => Some(MimeType::CSV),
+      "docx" => Some(MimeType::Word),
+      "gif" => Some(MimeType::Gif),
+      "epub" => Some(MimeType::EPUB),
+      "htm" | "html" => Some(MimeType::HTML),
+      "jpg" | "jpeg" => Some(MimeType::Jpeg),
+      "pdf" => Some(MimeType::PDF),
+      "odp" => Some(MimeType::ODP),
+      "ods" => Some(MimeType::ODS),
+      "odt" => Some(MimeType::ODT),
+      "png" => Some(MimeType::PNG),
+      "pptx" => Some(MimeType::PowerPoint),
+      "rtf" => Some(MimeType::RTF),
+      "svg" => Some(MimeType::SVG),
+      "tsv" => Some(MimeType::TSV),
+      "txt" => Some(MimeType::Text),
+      "xlsx" => Some(MimeType::Excel),
+      "zip" => Some(MimeType::Zip),
+      _ => None,
+    }
+  }
+}
+
+impl From<String> for MimeType {
+  fn from(value: String) -> MimeType {
+    MIME_TYPES
+      .iter()
+      .find(|(_, known)| *known == value)
+      .map(|(mime_type, _)| mime_type.clone())
+      .unwrap_or(MimeType::Other(value))
+  }
+}
+
+impl serde::Serialize for MimeType {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.to_string())
+  }
+}
+
+impl<'de> serde::Deserialize<'de> for MimeType {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<MimeType, D::Error> {
+    Ok(String::deserialize(deserializer)?.into())
   }
 }
 
@@ -87,6 +244,7 @@ pub struct File {
   pub parents: Option<Vec<String>>,
   /// Links for exporting Google Docs to specific formats.
   #[serde(rename = "exportLinks")]
+  pub export_links: Option<HashMap<String, String>>,
   /// A short description of the file.
   pub description: Option<String>,
   /// Identifies what kind of resource this is. Value: the fixed string "drive#file".
@@ -103,6 +261,62 @@ pub struct File {
   pub spaces: Option<Vec<String>>,
   /// Whether the file has been trashed, either explicitly or from a trashed parent folder. Only the owner may trash a file, and other users cannot see files in the owner's trash.
   pub trashed: Option<bool>,
+  /// The size of the file's content in bytes. This is only populated for files with binary content in Google Drive.
+  pub size: Option<String>,
+  /// The MD5 checksum for the content of the file. This is only applicable to files with binary content in Google Drive.
+  #[serde(rename = "md5Checksum")]
+  pub md5_checksum: Option<String>,
+  /// The time at which the file was created.
+  #[serde(rename = "createdTime")]
+  pub created_time: Option<DateTime<Utc>>,
+  /// The last time the file was modified by anyone.
+  #[serde(rename = "modifiedTime")]
+  pub modified_time: Option<DateTime<Utc>>,
+  /// The owner of this file. Only certain legacy files may have more than one owner. This field isn't populated for items in shared drives.
+  pub owners: Option<Vec<User>>,
+  /// The last user to modify the file.
+  #[serde(rename = "lastModifyingUser")]
+  pub last_modifying_user: Option<User>,
+  /// Whether the user has starred the file.
+  pub starred: Option<bool>,
+  /// A collection of arbitrary key-value pairs which are visible to all apps.
+  pub properties: Option<HashMap<String, String>>,
+  /// A collection of arbitrary key-value pairs which are private to the requesting app.
+  #[serde(rename = "appProperties")]
+  pub app_properties: Option<HashMap<String, String>>,
+  /// Shortcut file details. Only populated for shortcut files, which have the mimeType field set to application/vnd.google-apps.shortcut.
+  #[serde(rename = "shortcutDetails")]
+  pub shortcut_details: Option<ShortcutDetails>,
+}
+
+impl File {
+  /// Check if this is a Google Drive folder
+  pub fn is_folder(&self) -> bool {
+    self.mime_type == Some(MimeType::Folder)
+  }
+
+  /// Check if this is a shortcut to another file
+  pub fn is_shortcut(&self) -> bool {
+    self.mime_type == Some(MimeType::Shortcut)
+  }
+}
+
+/// What a shortcut points to
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ShortcutDetails {
+  /// The ID of the file that this shortcut points to.
+  #[serde(rename = "targetId")]
+  pub target_id: String,
+  /// The MIME type of the file that this shortcut points to. The value of this field is a snapshot of the target's MIME type, captured when the shortcut is created.
+  #[serde(rename = "targetMimeType", skip_serializing_if = "Option::is_none")]
+  pub target_mime_type: Option<MimeType>,
+}
+
+impl WrapiResult for File {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<File>, WrapiError> {
+    let result: File = parse_json(&headers, &body)?;
+    Ok(Box::new(result))
+  }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -126,6 +340,9 @@ pub struct FileSearchResult {
   pub kind: Kind,
   #[serde(rename = "incompleteSearch")]
   pub incomplete_search: bool,
+  /// Set when there are more results to fetch
+  #[serde(rename = "nextPageToken")]
+  pub next_page_token: Option<String>,
   pub files: Vec<File>,
 }
 
@@ -145,17 +362,71 @@ pub struct CreateFolder {
   pub mime_type: MimeType,
   /// The name of the file. This is not necessarily unique within a folder. Note that for immutable items such as the top level folders of shared drives, My Drive root folder, and Application Data folder the name is constant.
   pub name: String,
+  /// The IDs of the folders to create this one in
+  pub parents: Vec<String>,
+}
+
+impl WrapiRequest for CreateFolder {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        base_url,
+        &[("fields", file_fields().as_str()), SUPPORTS_ALL_DRIVES],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&self)?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CreateFile {
   /// If a file is created with a Google Doc MIME type, the uploaded content will be imported if possible. The supported import formats are published in the About resource.
+  /// Drive will detect the type from the content if this is left empty
+  #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+  pub mime_type: Option<MimeType>,
+  /// The name of the file. This is not necessarily unique within a folder. Note that for immutable items such as the top level folders of shared drives, My Drive root folder, and Application Data folder the name is constant.
+  pub name: String,
+  // The IDs of the parent folders
+  pub parents: Vec<String>,
+}
+
+/// Create a shortcut to another file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CreateShortcut {
   #[serde(rename = "mimeType")]
   pub mime_type: MimeType,
-  /// The name of the file. This is not necessarily unique within a folder. Note that for immutable items such as the top level folders of shared drives, My Drive root folder, and Application Data folder the name is constant.
   pub name: String,
-  // The ID of the parent
-  pub parent: String,
+  pub parents: Vec<String>,
+  #[serde(rename = "shortcutDetails")]
+  pub shortcut_details: ShortcutDetails,
+}
+
+impl WrapiRequest for CreateShortcut {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        base_url,
+        &[("fields", file_fields().as_str()), SUPPORTS_ALL_DRIVES],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&self)?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
 }
 
 // ******************************************
@@ -163,6 +434,12 @@ pub struct CreateFile {
 // *****              Calls             *****
 // *****                                *****
 // ******************************************
+/// Quote a value for a Drive query, escaping any quotes and backslashes inside of it
+pub fn quote(value: &str) -> String {
+  format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// A condition on a single field, which can be combined into boolean trees
 #[derive(Clone, Debug)]
 pub enum Filter {
   And(Box<(Filter, Filter)>),
@@ -173,43 +450,276 @@ pub enum Filter {
   Contains(String),
   Equals(String),
   StartsWith(String),
-  DateGT(String), // This should be a Chrono object
-  DateLT(String),
+  DateGT(DateTime<Utc>),
+  DateLT(DateTime<Utc>),
 }
 
 impl Filter {
-  pub fn to_string(&self) -> Result<String, WrapiError> {
+  /// Build the query clause comparing field against this filter
+  pub fn to_string(&self, field: &str) -> Result<String, DriveError> {
+    self.build(&|leaf| match leaf {
+      Filter::Equals(value) => Ok(format!("{} = {}", field, quote(value))),
+      Filter::Contains(value) => Ok(format!("{} contains {}", field, quote(value))),
+      Filter::StartsWith(_) => Err(DriveError::Unsupported(format!(
+        "{} can't be filtered with StartsWith, which Drive has no operator for",
+        field
+      ))),
+      Filter::DateGT(date) => Ok(format!("{} > {}", field, quote(&format_date(date)))),
+      Filter::DateLT(date) => Ok(format!("{} < {}", field, quote(&format_date(date)))),
+      _ => Err(DriveError::Invalid(format!(
+        "{:?} is not a comparison",
+        leaf
+      ))),
+    })
+  }
+
+  /// Build the query clause for a collection field like parents, which can only check membership
+  pub fn to_collection_string(&self, field: &str) -> Result<String, DriveError> {
+    self.build(&|leaf| match leaf {
+      Filter::Equals(value) => Ok(format!("{} in {}", quote(value), field)),
+      _ => Err(DriveError::Unsupported(format!(
+        "{} can only be filtered with Equals, not {:?}",
+        field, leaf
+      ))),
+    })
+  }
+
+  /// Walk the boolean tree, using leaf to build each of the comparisons
+  fn build(
+    &self,
+    leaf: &dyn Fn(&Filter) -> Result<String, DriveError>,
+  ) -> Result<String, DriveError> {
+    let join = |filters: Vec<&Filter>, op: &str| -> Result<String, DriveError> {
+      match filters.len() {
+        0 => Err(DriveError::Invalid(
+          "Any and All need at least one filter".to_string(),
+        )),
+        1 => filters[0].build(leaf),
+        _ => {
+          let clauses = filters
+            .iter()
+            .map(|filter| filter.build(leaf))
+            .collect::<Result<Vec<String>, DriveError>>()?;
+          Ok(format!("({})", clauses.join(op)))
+        }
+      }
+    };
+
     match self {
-      Filter::Equals(value) => Ok(format!("= '{}'", value)),
-      _ => Err(WrapiError::Json(
-        "Filter option not implemented".to_string(),
-      )),
+      Filter::And(pair) => join(vec![&pair.0, &pair.1], " and "),
+      Filter::Or(pair) => join(vec![&pair.0, &pair.1], " or "),
+      Filter::Not(filter) => Ok(format!("not {}", filter.build(leaf)?)),
+      Filter::Any(filters) => join(filters.iter().collect(), " or "),
+      Filter::All(filters) => join(filters.iter().collect(), " and "),
+      _ => leaf(self),
     }
   }
 }
 
-// TODO: Do we need to make a back reference to the filter for Not, Any, Or?
+/// Drive wants RFC 3339 dates, and treats them as UTC when there is no offset
+fn format_date(date: &DateTime<Utc>) -> String {
+  date.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+/// Who can find a file and how
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Visibility {
+  #[serde(rename = "anyoneCanFind")]
+  AnyoneCanFind,
+  #[serde(rename = "anyoneWithLink")]
+  AnyoneWithLink,
+  #[serde(rename = "domainCanFind")]
+  DomainCanFind,
+  #[serde(rename = "domainWithLink")]
+  DomainWithLink,
+  #[serde(rename = "limited")]
+  Limited,
+}
+
 #[derive(Clone, Debug)]
 pub enum FileFilter {
   Type(MimeType),
   Name(Filter),
+  /// Search the name, description and content of the files. Only Contains can be used
   FullText(Filter),
-  Parent(Filter), // TODO: Can this be context sensitive, rejecting Contains?
+  /// The ID of a folder the file is in. Only Equals can be used
+  Parent(Filter),
   IsSharedWithMe(bool),
+  /// When the file was last modified by anyone. Use DateGT/DateLT
+  ModifiedTime(Filter),
+  /// When the file was created. Use DateGT/DateLT
+  CreatedTime(Filter),
+  /// The email address of an owner. Only Equals can be used
+  Owner(Filter),
+  /// The email address of someone allowed to edit. Only Equals can be used
+  Writer(Filter),
+  /// The email address of someone allowed to read. Only Equals can be used
+  Reader(Filter),
+  Starred(bool),
+  /// A custom property visible to all apps, as (key, value)
+  Property(String, String),
+  /// A custom property private to the app that set it, as (key, value)
+  AppProperty(String, String),
+  Visibility(Visibility),
 }
 
 impl FileFilter {
-  pub fn to_string(&self) -> Result<String, WrapiError> {
+  pub fn to_string(&self) -> Result<String, DriveError> {
+    let has = |field: &str, key: &str, value: &str| {
+      format!(
+        "{} has {{ key={} and value={} }}",
+        field,
+        quote(key),
+        quote(value)
+      )
+    };
+
     match self {
-      FileFilter::Type(mime_type) => Ok(format!("mimeType = '{}'", mime_type.to_string())),
-      FileFilter::Name(filter) => Ok(format!("name {}", filter.to_string()?)),
-      _ => Err(WrapiError::Json(
-        "FileFilter option not implemented".to_string(),
+      FileFilter::Type(mime_type) => Ok(format!("mimeType = {}", quote(&mime_type.to_string()))),
+      FileFilter::Name(filter) => filter.to_string("name"),
+      FileFilter::FullText(filter) => filter.build(&|leaf| match leaf {
+        Filter::Contains(value) => Ok(format!("fullText contains {}", quote(value))),
+        _ => Err(DriveError::Unsupported(format!(
+          "fullText can only be filtered with Contains, not {:?}",
+          leaf
+        ))),
+      }),
+      FileFilter::Parent(filter) => filter.to_collection_string("parents"),
+      FileFilter::IsSharedWithMe(true) => Ok("sharedWithMe".to_string()),
+      FileFilter::IsSharedWithMe(false) => Ok("not sharedWithMe".to_string()),
+      FileFilter::ModifiedTime(filter) => filter.to_string("modifiedTime"),
+      FileFilter::CreatedTime(filter) => filter.to_string("createdTime"),
+      FileFilter::Owner(filter) => filter.to_collection_string("owners"),
+      FileFilter::Writer(filter) => filter.to_collection_string("writers"),
+      FileFilter::Reader(filter) => filter.to_collection_string("readers"),
+      FileFilter::Starred(starred) => Ok(format!("starred = {}", starred)),
+      FileFilter::Property(key, value) => Ok(has("properties", key, value)),
+      FileFilter::AppProperty(key, value) => Ok(has("appProperties", key, value)),
+      FileFilter::Visibility(visibility) => Ok(format!(
+        "visibility = {}",
+        quote(&serde_json::to_string(visibility)?.replace("\"", ""))
       )),
     }
   }
 }
 
+/// A field of a file that Drive can be asked to return
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+  Id,
+  Name,
+  MimeType,
+  Parents,
+  Trashed,
+  DriveId,
+  Size,
+  Md5Checksum,
+  CreatedTime,
+  ModifiedTime,
+  Owners,
+  LastModifyingUser,
+  Starred,
+  Properties,
+  AppProperties,
+  Description,
+  ExportLinks,
+  WebContentLink,
+  ShortcutDetails,
+}
+
+impl Field {
+  /// The name Drive uses for the field
+  pub fn name(&self) -> &'static str {
+    match self {
+      Field::Id => "id",
+      Field::Name => "name",
+      Field::MimeType => "mimeType",
+      Field::Parents => "parents",
+      Field::Trashed => "trashed",
+      Field::DriveId => "driveId",
+      Field::Size => "size",
+      Field::Md5Checksum => "md5Checksum",
+      Field::CreatedTime => "createdTime",
+      Field::ModifiedTime => "modifiedTime",
+      Field::Owners => "owners",
+      Field::LastModifyingUser => "lastModifyingUser",
+      Field::Starred => "starred",
+      Field::Properties => "properties",
+      Field::AppProperties => "appProperties",
+      Field::Description => "description",
+      Field::ExportLinks => "exportLinks",
+      Field::WebContentLink => "webContentLink",
+      Field::ShortcutDetails => "shortcutDetails",
+    }
+  }
+}
+
+/// Which fields of a file to ask Drive for, since smaller responses are faster
+///
+/// Every mask has the fields needed to find a file again: id, name, mimeType, parents, trashed,
+/// driveId and shortcutDetails. Add more with `with`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldMask {
+  fields: Vec<Field>,
+}
+
+impl Default for FieldMask {
+  fn default() -> FieldMask {
+    FieldMask::new()
+  }
+}
+
+impl FieldMask {
+  /// Only the fields needed to find a file again
+  pub fn new() -> FieldMask {
+    FieldMask {
+      fields: vec![
+        Field::Id,
+        Field::Name,
+        Field::MimeType,
+        Field::Parents,
+        Field::Trashed,
+        Field::DriveId,
+        Field::ShortcutDetails,
+      ],
+    }
+  }
+
+  /// What searches return unless told otherwise: the basics plus the size and checksum
+  pub fn standard() -> FieldMask {
+    FieldMask::new().with(Field::Size).with(Field::Md5Checksum)
+  }
+
+  /// Everything stat returns
+  pub fn stat() -> FieldMask {
+    FieldMask::standard()
+      .with(Field::CreatedTime)
+      .with(Field::ModifiedTime)
+      .with(Field::Owners)
+      .with(Field::LastModifyingUser)
+      .with(Field::Starred)
+      .with(Field::Properties)
+      .with(Field::AppProperties)
+  }
+
+  /// Also ask for field
+  pub fn with(mut self, field: Field) -> FieldMask {
+    if !self.fields.contains(&field) {
+      self.fields.push(field);
+    }
+    self
+  }
+}
+
+/// The mask as Drive's fields parameter for a single file
+impl std::fmt::Display for FieldMask {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let names: Vec<&str> = self.fields.iter().map(|field| field.name()).collect();
+    write!(f, "{}", names.join(","))
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum FileOpts {
   /// Include files that were deleted
   // default: false
@@ -218,39 +728,83 @@ pub enum FileOpts {
   Recursive(bool),
   /// Throw an error if any count other than one is found
   IsUnique(bool),
+  /// Create any missing parent folders, like `mkdir -p`
+  Parents(bool),
+  /// Ask for these fields of each file instead of FieldMask::standard
+  Fields(FieldMask),
 }
 
+#[derive(Clone, Debug)]
 pub struct FileRequest {
-  pub parent_id: String,
+  /// Limit the results to direct children of this folder. None searches everywhere
+  pub parent_id: Option<String>,
   pub filters: Vec<FileFilter>,
   pub opts: Vec<FileOpts>,
+  /// Where to continue from when fetching the next page of results
+  pub page_token: Option<String>,
+  /// Search the whole of this shared drive instead of My Drive. Searches limited to parent
+  /// folders don't need it, since they look in every drive
+  pub drive_id: Option<String>,
 }
 
-impl WrapiRequest for FileRequest {
-  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+impl FileRequest {
+  /// The q parameter Drive searches with, built from the filters, parent and trashed option
+  pub fn query(&self) -> Result<String, DriveError> {
     let mut query_params: Vec<String> = vec![];
     for filter in &self.filters {
       query_params.push(filter.to_string()?)
     }
-    query_params.push(String::from("trashed=false"));
-    let query = query_params.join(" and ");
+    if let Some(parent_id) = &self.parent_id {
+      query_params.push(format!("'{}' in parents", parent_id));
+    }
+    if !self.opts.contains(&FileOpts::IncludeTrashed(true)) {
+      query_params.push(String::from("trashed=false"));
+    }
+    Ok(query_params.join(" and "))
+  }
+}
+
+impl WrapiRequest for FileRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let query = self
+      .query()
+      .map_err(|err| WrapiError::Json(err.to_string()))?;
     println!("Query String: {}", query);
-    let uri = url::Url::parse_with_params(
-      base_url,
-      &[
-        ("q", &query[..]),
-        (
-          "fields",
-          "kind,nextPageToken,incompleteSearch,files/id,files/name,files/mimeType,files/parents",
-        ),
-      ],
-    )
-    .unwrap()
-    .into_string()
-    .parse()
-    .unwrap();
+    let fields = self
+      .opts
+      .iter()
+      .find_map(|opt| match opt {
+        FileOpts::Fields(fields) => Some(fields.clone()),
+        _ => None,
+      })
+      .unwrap_or_else(FieldMask::standard);
+    let mut params = vec![
+      ("q", query),
+      ("pageSize", "1000".to_string()),
+      (
+        "fields",
+        format!("kind,nextPageToken,incompleteSearch,files({})", fields),
+      ),
+      (SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string()),
+      ("includeItemsFromAllDrives", "true".to_string()),
+    ];
+    if let Some(page_token) = &self.page_token {
+      params.push(("pageToken", page_token.clone()));
+    }
+    let in_parents = self.parent_id.is_some()
+      || self
+        .filters
+        .iter()
+        .any(|filter| matches!(filter, FileFilter::Parent(_)));
+    if let Some(drive_id) = &self.drive_id {
+      params.push(("corpora", "drive".to_string()));
+      params.push(("driveId", drive_id.clone()));
+    } else if in_parents {
+      // The parents may be in a shared drive, and the parent clause keeps this from being slow
+      params.push(("corpora", "allDrives".to_string()));
+    }
 
-    Ok(uri)
+    Ok(url::Url::parse_with_params(base_url, &params)?.into())
   }
 
   fn build_body(&self) -> Result<String, WrapiError> {
@@ -265,14 +819,1481 @@ impl WrapiRequest for FileRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileResult {
   pub files: Vec<File>,
+  /// The token for the next page of results, if there is one
+  pub next_page_token: Option<String>,
 }
 
 impl WrapiResult for FileResult {
-  fn parse(_headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<FileResult>, WrapiError> {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<FileResult>, WrapiError> {
     // println!("Serde Result:\n{:#?}", std::str::from_utf8(&b ody));
-    let result: FileSearchResult = serde_json::from_str(std::str::from_utf8(&body)?)?;
+    let result: FileSearchResult = parse_json(&headers, &body)?;
     Ok(Box::new(FileResult {
       files: result.files,
+      next_page_token: result.next_page_token,
     }))
   }
 }
+
+/// Point to a file either by its path from the root or by its Drive ID
+#[derive(Clone, Debug)]
+pub enum FileRef {
+  Path(String),
+  Id(String),
+}
+
+/// Fetch the metadata of a single file by its ID
+#[derive(Clone, Debug)]
+pub struct GetRequest {
+  pub file_id: String,
+  /// The fields to return. None returns the same as a search
+  pub fields: Option<FieldMask>,
+}
+
+impl WrapiRequest for GetRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let fields = match &self.fields {
+      Some(fields) => fields.to_string(),
+      None => file_fields(),
+    };
+    Ok(
+      url::Url::parse_with_params(
+        &format!("{}/{}", base_url, self.file_id),
+        &[
+          ("fields", fields),
+          (SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string()),
+        ],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// The metadata to change on a file. Anything left as None is not modified
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct FileUpdate {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub trashed: Option<bool>,
+  /// Properties to set. A key set to None is removed from the file
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub properties: Option<HashMap<String, Option<String>>>,
+  /// The same as properties, but only visible to this app
+  #[serde(rename = "appProperties", skip_serializing_if = "Option::is_none")]
+  pub app_properties: Option<HashMap<String, Option<String>>>,
+}
+
+/// Modify a file's metadata and/or move it between folders
+#[derive(Clone, Debug)]
+pub struct UpdateRequest {
+  pub file_id: String,
+  pub update: FileUpdate,
+  /// IDs of folders to add the file to
+  pub add_parents: Vec<String>,
+  /// IDs of folders to remove the file from
+  pub remove_parents: Vec<String>,
+}
+
+impl WrapiRequest for UpdateRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![
+      ("fields", file_fields()),
+      (SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string()),
+    ];
+    if !self.add_parents.is_empty() {
+      params.push(("addParents", self.add_parents.join(",")));
+    }
+    if !self.remove_parents.is_empty() {
+      params.push(("removeParents", self.remove_parents.join(",")));
+    }
+    Ok(url::Url::parse_with_params(&format!("{}/{}", base_url, self.file_id), &params)?.into())
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&self.update)?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// Permanently delete a file, skipping the trash
+#[derive(Clone, Debug)]
+pub struct DeleteRequest {
+  pub file_id: String,
+}
+
+impl WrapiRequest for DeleteRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &format!("{}/{}", base_url, self.file_id),
+        &[SUPPORTS_ALL_DRIVES],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// Copy a single file into new parent folders
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CopyRequest {
+  #[serde(skip)]
+  pub file_id: String,
+  /// The name of the new copy
+  pub name: String,
+  /// The IDs of the folders to put the copy in
+  pub parents: Vec<String>,
+}
+
+impl WrapiRequest for CopyRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &format!("{}/{}/copy", base_url, self.file_id),
+        &[("fields", file_fields().as_str()), SUPPORTS_ALL_DRIVES],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&self)?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// The outcome of copying one item as part of a (possibly recursive) copy
+#[derive(Debug)]
+pub struct CopyResult {
+  /// The path of the original file or folder
+  pub source: String,
+  /// The path of the new copy
+  pub destination: String,
+  /// The newly created file, or why it could not be created
+  pub result: Result<File, DriveError>,
+}
+
+/// A response with no body, such as a successful delete
+#[derive(Debug)]
+pub struct EmptyResult {}
+
+impl WrapiResult for EmptyResult {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<EmptyResult>, WrapiError> {
+    // A successful call has nothing to say, so anything in the body is an error
+    if !body.is_empty() {
+      parse_json::<serde_json::Value>(&headers, &body)?;
+    }
+    Ok(Box::new(EmptyResult {}))
+  }
+}
+
+/// Ask for the token marking the current point in the drive's list of changes
+#[derive(Clone, Debug)]
+pub struct StartPageTokenRequest {
+  /// The shared drive to follow. None follows My Drive
+  pub drive_id: Option<String>,
+}
+
+impl WrapiRequest for StartPageTokenRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![(SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string())];
+    if let Some(drive_id) = &self.drive_id {
+      params.push(("driveId", drive_id.clone()));
+    }
+    Ok(url::Url::parse_with_params(&format!("{}/startPageToken", base_url), &params)?.into())
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartPageToken {
+  #[serde(rename = "startPageToken")]
+  pub start_page_token: String,
+}
+
+impl WrapiResult for StartPageToken {
+  fn parse(
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+  ) -> Result<Box<StartPageToken>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// List a page of the changes made since page_token
+#[derive(Clone, Debug)]
+pub struct ChangesRequest {
+  pub page_token: String,
+  /// The shared drive to follow. None follows My Drive
+  pub drive_id: Option<String>,
+}
+
+impl WrapiRequest for ChangesRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![
+      ("pageToken", self.page_token.clone()),
+      ("pageSize", "1000".to_string()),
+      (
+        "fields",
+        format!(
+          "nextPageToken,newStartPageToken,changes(fileId,removed,file({}))",
+          file_fields()
+        ),
+      ),
+      (SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string()),
+      ("includeItemsFromAllDrives", "true".to_string()),
+    ];
+    if let Some(drive_id) = &self.drive_id {
+      params.push(("driveId", drive_id.clone()));
+    }
+    Ok(url::Url::parse_with_params(base_url, &params)?.into())
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// One file that was created, modified or removed. file is only set when it still exists
+///
+/// Changes to the shared drives themselves come through too, with a change_type of "drive" and no
+/// file_id.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Change {
+  #[serde(rename = "changeType", default)]
+  pub change_type: Option<String>,
+  #[serde(rename = "fileId", default)]
+  pub file_id: Option<String>,
+  #[serde(default)]
+  pub removed: bool,
+  pub file: Option<File>,
+}
+
+/// A page of changes. new_start_page_token is only set on the last page, and is where to pick up
+/// the next time
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeList {
+  pub changes: Vec<Change>,
+  #[serde(rename = "nextPageToken")]
+  pub next_page_token: Option<String>,
+  #[serde(rename = "newStartPageToken")]
+  pub new_start_page_token: Option<String>,
+}
+
+impl WrapiResult for ChangeList {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<ChangeList>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// List a page of the shared drives the user is a member of
+#[derive(Clone, Debug)]
+pub struct DriveListRequest {
+  pub page_token: Option<String>,
+}
+
+impl WrapiRequest for DriveListRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![
+      ("pageSize", "100".to_string()),
+      ("fields", "nextPageToken,drives(id,name)".to_string()),
+    ];
+    if let Some(page_token) = &self.page_token {
+      params.push(("pageToken", page_token.clone()));
+    }
+    Ok(url::Url::parse_with_params(base_url, &params)?.into())
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// A shared drive. Its ID is also the ID of the folder at its root
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedDrive {
+  pub id: String,
+  pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DriveList {
+  #[serde(default)]
+  pub drives: Vec<SharedDrive>,
+  #[serde(rename = "nextPageToken")]
+  pub next_page_token: Option<String>,
+}
+
+impl WrapiResult for DriveList {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<DriveList>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// Files larger than this can't use a simple or multipart upload
+pub const MAX_MULTIPART_SIZE: usize = 5 * 1024 * 1024;
+/// Resumable uploads have to be sent in multiples of this size, except for the last chunk
+pub const UPLOAD_CHUNK_UNIT: usize = 256 * 1024;
+
+/// Where to read the content of an upload from
+pub enum UploadSource {
+  /// A file on the local file system
+  Path(std::path::PathBuf),
+  /// Any other stream of bytes
+  Reader(Box<dyn std::io::Read>),
+}
+
+/// How the content of an upload is sent to Drive
+#[derive(Clone, Debug, PartialEq)]
+pub enum UploadType {
+  /// Metadata and content together in a single request. Up to 5MB
+  Multipart,
+  /// A session that sends the content in chunks and can pick up where it left off
+  Resumable,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UploadOpts {
+  /// Force an upload type. By default files up to 5MB are Multipart and anything larger is Resumable
+  Method(UploadType),
+  /// Set the MIME type instead of guessing it from the file extension
+  MimeType(MimeType),
+  /// The number of bytes to send per resumable request, rounded down to a multiple of 256KB
+  // default: 10MB
+  ChunkSize(usize),
+}
+
+const UPLOAD_BOUNDARY: &str = "gappi_drive_fs_upload_boundary";
+
+/// A multipart/related body of the metadata and the content
+///
+/// Wrapi request bodies are strings, so binary content can't go through it as is. Here it is
+/// base64 encoded, and the chunks of a resumable upload skip Wrapi and go straight to the session
+/// URI, which is all the authorization the session needs.
+fn multipart_body(metadata: String, mime_type: &Option<MimeType>, content: &[u8]) -> String {
+  let content_type = match mime_type {
+    Some(mime_type) => mime_type.to_string(),
+    None => "application/octet-stream".to_string(),
+  };
+  format!(
+    "--{boundary}\r\n\
+     Content-Type: application/json; charset=UTF-8\r\n\r\n\
+     {metadata}\r\n\
+     --{boundary}\r\n\
+     Content-Type: {content_type}\r\n\
+     Content-Transfer-Encoding: base64\r\n\r\n\
+     {content}\r\n\
+     --{boundary}--",
+    boundary = UPLOAD_BOUNDARY,
+    metadata = metadata,
+    content_type = content_type,
+    content = base64::encode(content),
+  )
+}
+
+fn multipart_headers() -> Vec<(String, String)> {
+  vec![(
+    "Content-Type".to_string(),
+    format!("multipart/related; boundary={}", UPLOAD_BOUNDARY),
+  )]
+}
+
+/// Find a header by name, ignoring the case
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a String> {
+  headers
+    .iter()
+    .find(|(key, _)| key.to_lowercase() == name.to_lowercase())
+    .map(|(_, value)| value)
+}
+
+/// Send the metadata and the content together as a multipart/related body
+#[derive(Clone, Debug)]
+pub struct MultipartUploadRequest {
+  pub metadata: CreateFile,
+  pub content: Vec<u8>,
+}
+
+impl WrapiRequest for MultipartUploadRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        base_url,
+        &[
+          ("uploadType", "multipart"),
+          ("fields", file_fields().as_str()),
+          SUPPORTS_ALL_DRIVES,
+        ],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(multipart_body(
+      serde_json::to_string(&self.metadata)?,
+      &self.metadata.mime_type,
+      &self.content,
+    ))
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(multipart_headers())
+  }
+}
+
+/// Open a resumable upload session, which returns the URI to send the content to
+#[derive(Clone, Debug)]
+pub struct StartUploadRequest {
+  pub metadata: CreateFile,
+  /// The total size of the content, if it is known ahead of time
+  pub size: Option<u64>,
+}
+
+impl WrapiRequest for StartUploadRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        base_url,
+        &[
+          ("uploadType", "resumable"),
+          ("fields", file_fields().as_str()),
+          SUPPORTS_ALL_DRIVES,
+        ],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&self.metadata)?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    let mut headers = vec![];
+    if let Some(mime_type) = &self.metadata.mime_type {
+      headers.push(("X-Upload-Content-Type".to_string(), mime_type.to_string()));
+    }
+    if let Some(size) = self.size {
+      headers.push(("X-Upload-Content-Length".to_string(), size.to_string()));
+    }
+    Ok(headers)
+  }
+}
+
+/// An open resumable upload. Keep this around to resume the upload after a failure
+#[derive(Clone, Debug)]
+pub struct UploadSession {
+  /// The session URI that all the content gets sent to
+  pub uri: String,
+  /// The total size of the content, if it was known when the session started
+  pub size: Option<u64>,
+}
+
+impl WrapiResult for UploadSession {
+  fn parse(
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+  ) -> Result<Box<UploadSession>, WrapiError> {
+    match find_header(&headers, "Location") {
+      Some(uri) => Ok(Box::new(UploadSession {
+        uri: uri.clone(),
+        size: None,
+      })),
+      None => {
+        if !body.is_empty() {
+          parse_json::<serde_json::Value>(&headers, &body)?;
+        }
+        Err(WrapiError::Json(
+          "Drive did not return a resumable upload session URI".to_string(),
+        ))
+      }
+    }
+  }
+}
+
+/// Send part of the content to a resumable upload session
+///
+/// An empty chunk asks for the status of the session without sending anything
+#[derive(Clone, Debug)]
+pub struct UploadChunkRequest {
+  pub session_uri: String,
+  pub content: Vec<u8>,
+  /// The position of the first byte of the chunk in the whole upload
+  pub offset: u64,
+  /// The total size, which must be set for the final chunk
+  pub total: Option<u64>,
+}
+
+impl UploadChunkRequest {
+  /// Which bytes of the upload the chunk holds, out of the total if it's known
+  pub fn content_range(&self) -> String {
+    let total = match self.total {
+      Some(total) => total.to_string(),
+      None => "*".to_string(),
+    };
+    match self.content.len() {
+      0 => format!("bytes */{}", total),
+      len => format!(
+        "bytes {}-{}/{}",
+        self.offset,
+        self.offset + len as u64 - 1,
+        total
+      ),
+    }
+  }
+}
+
+/// Where a resumable upload is at after sending a chunk
+#[derive(Debug)]
+pub enum UploadStatus {
+  /// Drive has stored this many bytes and is waiting for the rest
+  Incomplete(u64),
+  /// All the content arrived and the file was created
+  Complete(Box<File>),
+}
+
+impl WrapiResult for UploadStatus {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<UploadStatus>, WrapiError> {
+    if let Ok(file) = serde_json::from_slice::<File>(&body) {
+      if file.id.is_some() {
+        return Ok(Box::new(UploadStatus::Complete(Box::new(file))));
+      }
+    }
+    if !body.is_empty() {
+      parse_json::<serde_json::Value>(&headers, &body)?;
+    }
+    // The Range header looks like "bytes=0-1234", and is missing if nothing has been stored yet
+    let received = match find_header(&headers, "Range") {
+      Some(range) => match range.rsplit('-').next().map(|end| end.parse::<u64>()) {
+        Some(Ok(end)) => end + 1,
+        _ => Err(WrapiError::Json(format!(
+          "Could not read the upload Range header '{}'",
+          range
+        )))?,
+      },
+      None => 0,
+    };
+    Ok(Box::new(UploadStatus::Incomplete(received)))
+  }
+}
+
+/// Download part of the content of a file, or of one of its revisions
+#[derive(Clone, Debug)]
+pub struct DownloadRequest {
+  pub file_id: String,
+  /// Get the content of this revision instead of the current one
+  pub revision_id: Option<String>,
+  /// The first and last byte to get, inclusive. None gets the whole file at once
+  pub range: Option<(u64, u64)>,
+}
+
+impl WrapiRequest for DownloadRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &match &self.revision_id {
+          Some(revision_id) => format!("{}/{}/revisions/{}", base_url, self.file_id, revision_id),
+          None => format!("{}/{}", base_url, self.file_id),
+        },
+        &[("alt", "media"), SUPPORTS_ALL_DRIVES],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(match self.range {
+      Some((start, end)) => vec![("Range".to_string(), format!("bytes={}-{}", start, end))],
+      None => vec![],
+    })
+  }
+}
+
+/// The raw bytes of a download
+#[derive(Debug)]
+pub struct MediaResult {
+  pub content: Vec<u8>,
+}
+
+impl WrapiResult for MediaResult {
+  /// Errors come back as JSON, so anything else is taken as content. A JSON file that isn't shaped
+  /// like a Google error is still content
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<MediaResult>, WrapiError> {
+    let is_json = match find_header(&headers, "Content-Type") {
+      Some(content_type) => content_type.starts_with("application/json"),
+      None => true,
+    };
+    if is_json {
+      api_retry::check_response(&headers, &body)?;
+    }
+    Ok(Box::new(MediaResult { content: body }))
+  }
+}
+
+/// Convert a Google native file to another format and download it
+#[derive(Clone, Debug)]
+pub struct ExportRequest {
+  pub file_id: String,
+  pub mime_type: MimeType,
+}
+
+impl WrapiRequest for ExportRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &format!("{}/{}/export", base_url, self.file_id),
+        &[("mimeType", self.mime_type.to_string())],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// A person who owns or changed a file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct User {
+  #[serde(rename = "displayName")]
+  pub display_name: Option<String>,
+  #[serde(rename = "emailAddress")]
+  pub email_address: Option<String>,
+  /// Whether this is the user making the request
+  pub me: Option<bool>,
+}
+
+const REVISION_FIELDS: &str =
+  "id,mimeType,modifiedTime,keepForever,size,md5Checksum,originalFilename,lastModifyingUser,exportLinks";
+
+/// One saved version of a file's content
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Revision {
+  pub id: String,
+  #[serde(rename = "mimeType")]
+  pub mime_type: Option<MimeType>,
+  #[serde(rename = "modifiedTime")]
+  pub modified_time: Option<DateTime<Utc>>,
+  /// Whether Drive keeps this revision even once newer ones would normally clear it out. Only
+  /// files with binary content have this
+  #[serde(rename = "keepForever")]
+  pub keep_forever: Option<bool>,
+  pub size: Option<String>,
+  #[serde(rename = "md5Checksum")]
+  pub md5_checksum: Option<String>,
+  #[serde(rename = "originalFilename")]
+  pub original_filename: Option<String>,
+  #[serde(rename = "lastModifyingUser")]
+  pub last_modifying_user: Option<User>,
+  /// For Google native files, where to download this revision as each export format
+  #[serde(rename = "exportLinks")]
+  pub export_links: Option<HashMap<String, String>>,
+}
+
+impl WrapiResult for Revision {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<Revision>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// List a page of a file's revisions, oldest first
+#[derive(Clone, Debug)]
+pub struct RevisionListRequest {
+  pub file_id: String,
+  pub page_token: Option<String>,
+}
+
+impl WrapiRequest for RevisionListRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![(
+      "fields",
+      format!("nextPageToken,revisions({})", REVISION_FIELDS),
+    )];
+    if let Some(page_token) = &self.page_token {
+      params.push(("pageToken", page_token.clone()));
+    }
+    Ok(
+      url::Url::parse_with_params(&format!("{}/{}/revisions", base_url, self.file_id), &params)?
+        .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionList {
+  #[serde(default)]
+  pub revisions: Vec<Revision>,
+  #[serde(rename = "nextPageToken")]
+  pub next_page_token: Option<String>,
+}
+
+impl WrapiResult for RevisionList {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<RevisionList>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// Get the metadata of a revision
+#[derive(Clone, Debug)]
+pub struct RevisionRequest {
+  pub file_id: String,
+  pub revision_id: String,
+}
+
+impl WrapiRequest for RevisionRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let params = [("fields", REVISION_FIELDS)];
+    Ok(
+      url::Url::parse_with_params(
+        &format!(
+          "{}/{}/revisions/{}",
+          base_url, self.file_id, self.revision_id
+        ),
+        &params,
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// Download a revision of a Google native file from one of its export links
+#[derive(Clone, Debug)]
+pub struct RevisionExportRequest {
+  pub export_link: String,
+}
+
+impl WrapiRequest for RevisionExportRequest {
+  fn build_uri(&self, _base_url: &str) -> Result<String, WrapiError> {
+    Ok(self.export_link.clone())
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RevisionUpdate {
+  #[serde(rename = "keepForever")]
+  keep_forever: bool,
+}
+
+/// Pin a revision so Drive keeps it, or unpin it so it gets cleared out like the rest
+#[derive(Clone, Debug)]
+pub struct UpdateRevisionRequest {
+  pub file_id: String,
+  pub revision_id: String,
+  pub keep_forever: bool,
+}
+
+impl WrapiRequest for UpdateRevisionRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &format!(
+          "{}/{}/revisions/{}",
+          base_url, self.file_id, self.revision_id
+        ),
+        &[("fields", REVISION_FIELDS)],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&RevisionUpdate {
+      keep_forever: self.keep_forever,
+    })?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// Delete a revision of a file with binary content. Google native files can't have revisions deleted
+#[derive(Clone, Debug)]
+pub struct DeleteRevisionRequest {
+  pub file_id: String,
+  pub revision_id: String,
+}
+
+impl WrapiRequest for DeleteRevisionRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(format!(
+      "{}/{}/revisions/{}",
+      base_url, self.file_id, self.revision_id
+    ))
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+const REPLY_FIELDS: &str = "id,content,author,createdTime,modifiedTime,action";
+const COMMENT_FIELDS: &str = "id,content,author,createdTime,modifiedTime,resolved,anchor,quotedFileContent,replies(id,content,author,createdTime,modifiedTime,action)";
+
+/// The part of a document a comment was made on
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuotedFileContent {
+  #[serde(rename = "mimeType")]
+  pub mime_type: String,
+  pub value: String,
+}
+
+/// A comment on a file, along with the replies to it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Comment {
+  pub id: String,
+  pub content: Option<String>,
+  pub author: Option<User>,
+  #[serde(rename = "createdTime")]
+  pub created_time: Option<DateTime<Utc>>,
+  #[serde(rename = "modifiedTime")]
+  pub modified_time: Option<DateTime<Utc>>,
+  pub resolved: Option<bool>,
+  /// Where in the file the comment points to, as the JSON string Drive uses for anchors
+  pub anchor: Option<String>,
+  #[serde(rename = "quotedFileContent")]
+  pub quoted_file_content: Option<QuotedFileContent>,
+  #[serde(default)]
+  pub replies: Vec<Reply>,
+}
+
+impl WrapiResult for Comment {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<Comment>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// What a reply does to the comment it answers, besides adding to the thread
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReplyAction {
+  #[serde(rename = "resolve")]
+  Resolve,
+  #[serde(rename = "reopen")]
+  Reopen,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Reply {
+  pub id: String,
+  pub content: Option<String>,
+  pub author: Option<User>,
+  #[serde(rename = "createdTime")]
+  pub created_time: Option<DateTime<Utc>>,
+  #[serde(rename = "modifiedTime")]
+  pub modified_time: Option<DateTime<Utc>>,
+  pub action: Option<ReplyAction>,
+}
+
+impl WrapiResult for Reply {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<Reply>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// List a page of the comments on a file
+#[derive(Clone, Debug)]
+pub struct CommentListRequest {
+  pub file_id: String,
+  pub page_token: Option<String>,
+}
+
+impl WrapiRequest for CommentListRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![
+      ("pageSize", "100".to_string()),
+      (
+        "fields",
+        format!("nextPageToken,comments({})", COMMENT_FIELDS),
+      ),
+    ];
+    if let Some(page_token) = &self.page_token {
+      params.push(("pageToken", page_token.clone()));
+    }
+    Ok(
+      url::Url::parse_with_params(&format!("{}/{}/comments", base_url, self.file_id), &params)?
+        .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentList {
+  #[serde(default)]
+  pub comments: Vec<Comment>,
+  #[serde(rename = "nextPageToken")]
+  pub next_page_token: Option<String>,
+}
+
+impl WrapiResult for CommentList {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<CommentList>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// A comment to add to a file
+///
+/// anchor is the JSON string Drive uses to point at a region of the file. Docs doesn't show
+/// anchors made through the API as highlights, so set quoted_file_content to the text being
+/// commented on as well.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NewComment {
+  pub content: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub anchor: Option<String>,
+  #[serde(rename = "quotedFileContent", skip_serializing_if = "Option::is_none")]
+  pub quoted_file_content: Option<QuotedFileContent>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CreateCommentRequest {
+  pub file_id: String,
+  pub comment: NewComment,
+}
+
+impl WrapiRequest for CreateCommentRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &format!("{}/{}/comments", base_url, self.file_id),
+        &[("fields", COMMENT_FIELDS)],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&self.comment)?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct NewReply {
+  content: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  action: Option<ReplyAction>,
+}
+
+/// Answer a comment, optionally resolving or reopening it at the same time
+#[derive(Clone, Debug)]
+pub struct CreateReplyRequest {
+  pub file_id: String,
+  pub comment_id: String,
+  pub content: String,
+  pub action: Option<ReplyAction>,
+}
+
+impl WrapiRequest for CreateReplyRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &format!(
+          "{}/{}/comments/{}/replies",
+          base_url, self.file_id, self.comment_id
+        ),
+        &[("fields", REPLY_FIELDS)],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&NewReply {
+      content: self.content.clone(),
+      action: self.action.clone(),
+    })?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+const PERMISSION_FIELDS: &str = "id,type,role,emailAddress,domain,displayName";
+
+/// What kind of grantee a permission is for
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PermissionType {
+  #[serde(rename = "user")]
+  User,
+  #[serde(rename = "group")]
+  Group,
+  #[serde(rename = "domain")]
+  Domain,
+  #[serde(rename = "anyone")]
+  Anyone,
+}
+
+/// What a permission allows. Organizer and FileOrganizer only exist on shared drives
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Role {
+  #[serde(rename = "owner")]
+  Owner,
+  #[serde(rename = "organizer")]
+  Organizer,
+  #[serde(rename = "fileOrganizer")]
+  FileOrganizer,
+  #[serde(rename = "writer")]
+  Writer,
+  #[serde(rename = "commenter")]
+  Commenter,
+  #[serde(rename = "reader")]
+  Reader,
+}
+
+/// Who to share a file with
+#[derive(Clone, Debug, PartialEq)]
+pub enum Grantee {
+  /// A user, by email address
+  User(String),
+  /// A Google group, by email address
+  Group(String),
+  /// Everyone in a domain, like "example.com"
+  Domain(String),
+  /// Anyone with the link
+  Anyone,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Permission {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  #[serde(rename = "type")]
+  pub permission_type: PermissionType,
+  pub role: Role,
+  #[serde(rename = "emailAddress", skip_serializing_if = "Option::is_none")]
+  pub email_address: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub domain: Option<String>,
+  #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+  pub display_name: Option<String>,
+}
+
+impl Permission {
+  /// A new permission giving role to grantee
+  pub fn new(grantee: Grantee, role: Role) -> Permission {
+    let (permission_type, email_address, domain) = match grantee {
+      Grantee::User(email) => (PermissionType::User, Some(email), None),
+      Grantee::Group(email) => (PermissionType::Group, Some(email), None),
+      Grantee::Domain(domain) => (PermissionType::Domain, None, Some(domain)),
+      Grantee::Anyone => (PermissionType::Anyone, None, None),
+    };
+    Permission {
+      id: None,
+      permission_type,
+      role,
+      email_address,
+      domain,
+      display_name: None,
+    }
+  }
+}
+
+impl WrapiResult for Permission {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<Permission>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShareOpts {
+  /// Email the users or groups being shared with. Drive does this by default, and always does for
+  /// a change of owner
+  SendNotification(bool),
+  /// A message to add to the notification email
+  EmailMessage(String),
+}
+
+/// List a page of who a file is shared with
+#[derive(Clone, Debug)]
+pub struct PermissionListRequest {
+  pub file_id: String,
+  pub page_token: Option<String>,
+}
+
+impl WrapiRequest for PermissionListRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![
+      (
+        "fields",
+        format!("nextPageToken,permissions({})", PERMISSION_FIELDS),
+      ),
+      (SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string()),
+    ];
+    if let Some(page_token) = &self.page_token {
+      params.push(("pageToken", page_token.clone()));
+    }
+    Ok(
+      url::Url::parse_with_params(
+        &format!("{}/{}/permissions", base_url, self.file_id),
+        &params,
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PermissionList {
+  #[serde(default)]
+  pub permissions: Vec<Permission>,
+  #[serde(rename = "nextPageToken")]
+  pub next_page_token: Option<String>,
+}
+
+impl WrapiResult for PermissionList {
+  fn parse(
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+  ) -> Result<Box<PermissionList>, WrapiError> {
+    Ok(Box::new(parse_json(&headers, &body)?))
+  }
+}
+
+/// Share a file. Giving the Owner role transfers ownership of the file
+#[derive(Clone, Debug)]
+pub struct CreatePermissionRequest {
+  pub file_id: String,
+  pub permission: Permission,
+  pub opts: Vec<ShareOpts>,
+}
+
+impl WrapiRequest for CreatePermissionRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![
+      ("fields", PERMISSION_FIELDS.to_string()),
+      (SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string()),
+    ];
+    if self.permission.role == Role::Owner {
+      params.push(("transferOwnership", "true".to_string()));
+    }
+    for opt in &self.opts {
+      match opt {
+        ShareOpts::SendNotification(send) => {
+          params.push(("sendNotificationEmail", send.to_string()))
+        }
+        ShareOpts::EmailMessage(message) => params.push(("emailMessage", message.clone())),
+      }
+    }
+    Ok(
+      url::Url::parse_with_params(
+        &format!("{}/{}/permissions", base_url, self.file_id),
+        &params,
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&self.permission)?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct PermissionUpdate {
+  role: Role,
+}
+
+/// Change the role of an existing permission. Changing it to Owner transfers ownership of the file
+#[derive(Clone, Debug)]
+pub struct UpdatePermissionRequest {
+  pub file_id: String,
+  pub permission_id: String,
+  pub role: Role,
+}
+
+impl WrapiRequest for UpdatePermissionRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    let mut params = vec![
+      ("fields", PERMISSION_FIELDS.to_string()),
+      (SUPPORTS_ALL_DRIVES.0, SUPPORTS_ALL_DRIVES.1.to_string()),
+    ];
+    if self.role == Role::Owner {
+      params.push(("transferOwnership", "true".to_string()));
+    }
+    Ok(
+      url::Url::parse_with_params(
+        &format!(
+          "{}/{}/permissions/{}",
+          base_url, self.file_id, self.permission_id
+        ),
+        &params,
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok(serde_json::to_string(&PermissionUpdate {
+      role: self.role.clone(),
+    })?)
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+/// Stop sharing a file with the grantee of a permission
+#[derive(Clone, Debug)]
+pub struct DeletePermissionRequest {
+  pub file_id: String,
+  pub permission_id: String,
+}
+
+impl WrapiRequest for DeletePermissionRequest {
+  fn build_uri(&self, base_url: &str) -> Result<String, WrapiError> {
+    Ok(
+      url::Url::parse_with_params(
+        &format!(
+          "{}/{}/permissions/{}",
+          base_url, self.file_id, self.permission_id
+        ),
+        &[SUPPORTS_ALL_DRIVES],
+      )?
+      .into(),
+    )
+  }
+
+  fn build_body(&self) -> Result<String, WrapiError> {
+    Ok("".to_string())
+  }
+
+  fn build_headers(&self) -> Result<Vec<(String, String)>, WrapiError> {
+    Ok(vec![])
+  }
+}
+
+// ******************************************
+// *****                                *****
+// *****             Errors             *****
+// *****                                *****
+// ******************************************
+
+/// Everything that can go wrong in a DriveFS call, so callers can match on what happened
+#[derive(Debug)]
+pub enum DriveError {
+  /// Nothing exists at the path or ID
+  NotFound(String),
+  /// A search that had to find exactly one file found none. Holds the query and where it looked
+  NoMatch(String),
+  /// The path or search matched more than one file. Holds the IDs of every candidate
+  Ambiguous { path: String, ids: Vec<String> },
+  /// Something is already at the path a new file or folder was going to take
+  AlreadyExists(String),
+  /// The user isn't allowed to see or change the file
+  PermissionDenied(String),
+  /// Out of storage, or over a daily limit for the project
+  QuotaExceeded(String),
+  /// Too many requests too quickly. Trying again after a pause should work
+  RateLimited(String),
+  /// Drive can't do what was asked, like exporting a Sheet as a Word document
+  Unsupported(String),
+  /// The request doesn't make sense as it stands, like moving a folder inside of itself
+  Invalid(String),
+  /// Reading or writing the local side of a transfer failed, or the content came back damaged
+  Io(String),
+  /// A resumable upload kept failing part way through. Pass the session to resume_upload to send
+  /// the rest
+  UploadInterrupted {
+    session: UploadSession,
+    error: Box<DriveError>,
+  },
+  /// Any other error Google sent back, with its HTTP status code and reason
+  Api {
+    code: u16,
+    reason: String,
+    message: String,
+  },
+  /// The call failed without an answer from Google, or the answer couldn't be read
+  Wrapi(WrapiError),
+}
+
+impl std::fmt::Display for DriveError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      DriveError::NotFound(path) => write!(f, "not found: {}", path),
+      DriveError::NoMatch(query) => write!(f, "no match: {}", query),
+      DriveError::Ambiguous { path, ids } => write!(
+        f,
+        "ambiguous: {} matches for '{}' ({})",
+        ids.len(),
+        path,
+        ids.join(", ")
+      ),
+      DriveError::AlreadyExists(path) => write!(f, "already exists: {}", path),
+      DriveError::PermissionDenied(message) => write!(f, "permission denied: {}", message),
+      DriveError::QuotaExceeded(message) => write!(f, "quota exceeded: {}", message),
+      DriveError::RateLimited(message) => write!(f, "rate limited: {}", message),
+      DriveError::Unsupported(message) => write!(f, "unsupported: {}", message),
+      DriveError::Invalid(message) => write!(f, "{}", message),
+      DriveError::Io(message) => write!(f, "{}", message),
+      DriveError::UploadInterrupted { session, error } => write!(
+        f,
+        "upload interrupted, resume it with the session '{}': {}",
+        session.uri, error
+      ),
+      DriveError::Api {
+        code,
+        reason,
+        message,
+      } => write!(f, "Drive error {} ({}): {}", code, reason, message),
+      DriveError::Wrapi(err) => write!(f, "{:?}", err),
+    }
+  }
+}
+
+impl std::error::Error for DriveError {}
+
+impl From<WrapiError> for DriveError {
+  fn from(err: WrapiError) -> DriveError {
+    let message = if let WrapiError::General(message) = &err {
+      message
+    } else if let WrapiError::Json(message) = &err {
+      message
+    } else {
+      return DriveError::Wrapi(err);
+    };
+    match api_retry::google_error(message) {
+      Some(google) => google.into(),
+      None => DriveError::Wrapi(err),
+    }
+  }
+}
+
+impl From<serde_json::Error> for DriveError {
+  fn from(err: serde_json::Error) -> DriveError {
+    DriveError::Wrapi(err.into())
+  }
+}
+
+impl From<api_retry::GoogleError> for DriveError {
+  fn from(error: api_retry::GoogleError) -> DriveError {
+    let api_retry::GoogleError {
+      code,
+      reason,
+      message,
+    } = error;
+    match (code, reason.as_str()) {
+      (404, _) => DriveError::NotFound(message),
+      (429, _) | (_, "rateLimitExceeded") | (_, "userRateLimitExceeded") => {
+        DriveError::RateLimited(message)
+      }
+      (_, "quotaExceeded") | (_, "storageQuotaExceeded") | (_, "dailyLimitExceeded") => {
+        DriveError::QuotaExceeded(message)
+      }
+      (401, _) | (403, _) => DriveError::PermissionDenied(message),
+      (code, _) => DriveError::Api {
+        code,
+        reason,
+        message,
+      },
+    }
+  }
+}
+
+/// Read a JSON result, turning a Google error body into an error instead of a confusing failure to
+/// find the expected fields
+fn parse_json<T: DeserializeOwned>(
+  headers: &[(String, String)],
+  body: &[u8],
+) -> Result<T, WrapiError> {
+  api_retry::check_response(headers, body)?;
+  Ok(serde_json::from_str(std::str::from_utf8(body)?)?)
+}
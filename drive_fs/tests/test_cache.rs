@@ -0,0 +1,151 @@
+use drive_fs::models::{ChangeList, DriveError};
+use drive_fs::{Call, DriveFS, FileCache, Response, RetryPolicy};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wrapi::{WrapiError, WrapiResult};
+
+fn folder_change(id: &str, name: &str, parent: &str, trashed: bool) -> String {
+  format!(
+    r#"{{"fileId": "{id}", "file": {{"id": "{id}", "name": "{name}", "parents": ["{parent}"], "trashed": {trashed}, "mimeType": "application/vnd.google-apps.folder"}}}}"#,
+    id = id,
+    name = name,
+    parent = parent,
+    trashed = trashed
+  )
+}
+
+fn apply(cache: &mut FileCache, changes: Vec<String>) {
+  let body = format!(
+    r#"{{"newStartPageToken": "2", "changes": [{}]}}"#,
+    changes.join(",")
+  );
+  let page = ChangeList::parse(vec![], body.into_bytes()).unwrap();
+  cache.apply_changes(&page.changes).unwrap();
+}
+
+#[test]
+fn test_out_of_order_changes() {
+  let mut cache: FileCache = serde_json::from_str(
+    r#"{
+      "root_id": "r",
+      "graph_cache": {"r": {"id": "r", "name": "root", "parents": [], "children": []}},
+      "page_token": "1",
+      "drive_id": null
+    }"#,
+  )
+  .unwrap();
+
+  // The child comes before the folder it is in
+  apply(
+    &mut cache,
+    vec![
+      folder_change("c", "Q1", "b", false),
+      folder_change("b", "Reports", "a", false),
+      folder_change("a", "Finance", "r", false),
+    ],
+  );
+  assert_eq!(cache.folder_ids("/Finance"), vec!["a".to_string()]);
+  assert_eq!(
+    cache.folder_ids("/Finance/Reports/Q1"),
+    vec!["c".to_string()]
+  );
+
+  // Trashing a folder only reports the folder, and restoring it brings back what was under it
+  apply(&mut cache, vec![folder_change("b", "Reports", "a", true)]);
+  assert!(cache.folder_ids("/Finance/Reports").is_empty());
+  assert!(cache.folder_ids("/Finance/Reports/Q1").is_empty());
+  apply(&mut cache, vec![folder_change("b", "Reports", "a", false)]);
+  assert_eq!(
+    cache.folder_ids("/Finance/Reports/Q1"),
+    vec!["c".to_string()]
+  );
+
+  // Changes to shared drives are skipped
+  apply(
+    &mut cache,
+    vec![
+      r#"{"changeType": "drive", "driveId": "0AB", "removed": true}"#.to_string(),
+      r#"{"removed": false}"#.to_string(),
+    ],
+  );
+  assert_eq!(cache.folder_ids("/Finance"), vec!["a".to_string()]);
+}
+
+/// A DriveFS that answers with body for each endpoint in answers, keeping the URI of every call
+fn answering(answers: Vec<(&'static str, &'static str)>) -> (DriveFS, Rc<RefCell<Vec<String>>>) {
+  let calls = Rc::new(RefCell::new(vec![]));
+  let seen = calls.clone();
+  let drive = DriveFS::with_responder(move |call: &Call| -> Result<Response, WrapiError> {
+    seen.borrow_mut().push(call.uri.clone());
+    match answers
+      .iter()
+      .find(|(endpoint, _)| *endpoint == call.endpoint)
+    {
+      Some((_, body)) => Ok((vec![], body.as_bytes().to_vec())),
+      None => Err(WrapiError::General(format!(
+        "Unexpected call to {}",
+        call.uri
+      ))),
+    }
+  })
+  .retry_policy(RetryPolicy::none());
+  (drive, calls)
+}
+
+fn cache_path(name: &str) -> std::path::PathBuf {
+  std::env::temp_dir().join(format!("drive_fs_{}_{}.json", name, std::process::id()))
+}
+
+#[test]
+fn test_save_and_restore() {
+  let (drive, _) = answering(vec![
+    ("start_page_token", r#"{"startPageToken": "41"}"#),
+    (
+      "find",
+      r#"{"kind": "drive#fileList", "incompleteSearch": false, "files": [
+        {"id": "a", "name": "Finance", "parents": ["r"], "mimeType": "application/vnd.google-apps.folder"},
+        {"id": "b", "name": "Reports", "parents": ["a"], "mimeType": "application/vnd.google-apps.folder"}
+      ]}"#,
+    ),
+  ]);
+  let drive = drive.load_cache().unwrap();
+  let path = cache_path("save_and_restore");
+  drive.save_cache(&path).unwrap();
+
+  // Only the changes since the save are asked for, starting from the saved token
+  let (restored, calls) = answering(vec![(
+    "changes",
+    r#"{"newStartPageToken": "42", "changes": []}"#,
+  )]);
+  let restored = restored.load_cache_from(&path).unwrap();
+  assert_eq!(restored.path_of("b").unwrap(), "/Finance/Reports");
+  let calls = calls.borrow();
+  assert_eq!(calls.len(), 1);
+  assert!(calls[0].contains("pageToken=41"));
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_restore_other_version() {
+  let path = cache_path("other_version");
+  std::fs::write(
+    &path,
+    r#"{"version": 1, "cache": {"root_id": "r", "graph_cache": {}, "page_token": "1", "drive_id": null}}"#,
+  )
+  .unwrap();
+  let (drive, calls) = answering(vec![]);
+  match drive.load_cache_from(&path) {
+    Err(DriveError::Unsupported(_)) => (),
+    other => panic!("Expected Unsupported, got {:?}", other),
+  }
+  assert!(calls.borrow().is_empty());
+
+  // Not a saved cache at all
+  std::fs::write(&path, r#"{"root_id": "r"}"#).unwrap();
+  let (drive, _) = answering(vec![]);
+  match drive.load_cache_from(&path) {
+    Err(DriveError::Invalid(_)) => (),
+    other => panic!("Expected Invalid, got {:?}", other),
+  }
+  std::fs::remove_file(&path).unwrap();
+}
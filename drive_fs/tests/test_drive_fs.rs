@@ -0,0 +1,1081 @@
+use drive_fs::models::{
+  DriveError, File, FileFilter, FileOpts, FileRef, Filter, NewComment, ReplyAction, UploadOpts,
+  UploadSource, UploadType, UPLOAD_CHUNK_UNIT,
+};
+use drive_fs::{Call, DriveFS, RetryPolicy, WrapiError};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const FOLDER: &str = "application/vnd.google-apps.folder";
+
+/// A canned response, given to the first call to endpoint whose URI and body contain every needle
+struct Route {
+  endpoint: &'static str,
+  needles: Vec<String>,
+  headers: Vec<(String, String)>,
+  body: String,
+  // Fail the call with this message instead of answering
+  error: Option<String>,
+}
+
+impl Route {
+  fn header(mut self, name: &str, value: &str) -> Route {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+}
+
+fn on(endpoint: &'static str, needles: &[&str], body: String) -> Route {
+  Route {
+    endpoint,
+    needles: needles.iter().map(|needle| needle.to_string()).collect(),
+    headers: vec![],
+    body,
+    error: None,
+  }
+}
+
+/// A route that fails with a server error, saying to retry straight away
+fn fail(endpoint: &'static str, needles: &[&str]) -> Route {
+  Route {
+    error: Some(format!("Retry-After: 0\n{}", SERVER_ERROR)),
+    ..on(endpoint, needles, String::new())
+  }
+}
+
+const SERVER_ERROR: &str =
+  r#"{"error": {"code": 503, "message": "Backend Error", "errors": [{"reason": "backendError"}]}}"#;
+
+/// Stands in for Drive, answering each call with the first route that matches and then dropping it
+struct Fake {
+  routes: Rc<RefCell<Vec<Route>>>,
+  calls: Rc<RefCell<Vec<Call>>>,
+}
+
+impl Fake {
+  fn drive(routes: Vec<Route>) -> (DriveFS, Fake) {
+    let fake = Fake {
+      routes: Rc::new(RefCell::new(routes)),
+      calls: Rc::new(RefCell::new(vec![])),
+    };
+    let (routes, calls) = (fake.routes.clone(), fake.calls.clone());
+    let drive = DriveFS::with_responder(move |call: &Call| {
+      calls.borrow_mut().push(call.clone());
+      let text = request_text(call);
+      let mut routes = routes.borrow_mut();
+      let i = routes
+        .iter()
+        .position(|route| {
+          route.endpoint == call.endpoint
+            && route.needles.iter().all(|needle| text.contains(needle))
+        })
+        .ok_or_else(|| WrapiError::General(format!("No response for {}", text)))?;
+      let route = routes.remove(i);
+      match route.error {
+        Some(message) => Err(WrapiError::General(message)),
+        None => Ok((route.headers, route.body.into_bytes())),
+      }
+    })
+    .retry_policy(RetryPolicy::none());
+    (drive, fake)
+  }
+
+  /// How many calls were made to endpoint
+  fn count(&self, endpoint: &str) -> usize {
+    let calls = self.calls.borrow();
+    calls
+      .iter()
+      .filter(|call| call.endpoint == endpoint)
+      .count()
+  }
+
+  /// Check every route was used
+  fn done(&self) {
+    let routes = self.routes.borrow();
+    let unused: Vec<String> = routes
+      .iter()
+      .map(|route| route.needles.join(", "))
+      .collect();
+    assert!(unused.is_empty(), "Unused routes: {:?}", unused);
+  }
+}
+
+/// The endpoint, path, decoded query, headers and body of a call, for the needles to search
+fn request_text(call: &Call) -> String {
+  let uri = url::Url::parse(&call.uri).unwrap();
+  let query: Vec<String> = uri
+    .query_pairs()
+    .map(|(key, value)| format!("{}={}", key, value))
+    .collect();
+  let headers: Vec<String> = call
+    .headers
+    .iter()
+    .map(|(name, value)| format!("{}: {}", name, value))
+    .collect();
+  format!(
+    "{} {}?{} {} {}",
+    call.endpoint,
+    uri.path(),
+    query.join("&"),
+    headers.join(" "),
+    String::from_utf8_lossy(&call.body)
+  )
+}
+
+fn item(id: &str, name: &str, parent: &str, mime_type: &str) -> String {
+  format!(
+    r#"{{"id": "{}", "name": "{}", "parents": ["{}"], "mimeType": "{}"}}"#,
+    id, name, parent, mime_type
+  )
+}
+
+fn folder(id: &str, name: &str, parent: &str) -> String {
+  item(id, name, parent, FOLDER)
+}
+
+fn text_file(id: &str, name: &str, parent: &str) -> String {
+  item(id, name, parent, "text/plain")
+}
+
+fn files(items: &[String]) -> String {
+  format!(
+    r#"{{"kind": "drive#fileList", "incompleteSearch": false, "files": [{}]}}"#,
+    items.join(",")
+  )
+}
+
+fn names(files: &[drive_fs::models::File]) -> Vec<String> {
+  files
+    .iter()
+    .map(|file| file.name.clone().unwrap_or_default())
+    .collect()
+}
+
+#[test]
+fn test_ls_resolves_path_a_folder_at_a_time() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'Projects'", "'root' in parents", FOLDER],
+      files(&[folder("p", "Projects", "r")]),
+    ),
+    on(
+      "find",
+      &["name = 'Docs'", "'p' in parents", FOLDER],
+      files(&[folder("d", "Docs", "p")]),
+    ),
+    on(
+      "find",
+      &["q='d' in parents and trashed=false"],
+      files(&[text_file("f", "notes.txt", "d"), folder("s", "Old", "d")]),
+    ),
+  ]);
+
+  let listing = drive.ls("/Projects/Docs/", vec![]).unwrap();
+  assert_eq!(names(&listing.files), vec!["notes.txt", "Old"]);
+  fake.done();
+}
+
+#[test]
+fn test_ls_recursive() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["q='root' in parents"],
+      files(&[folder("a", "A", "r"), text_file("f1", "one.txt", "r")]),
+    ),
+    on(
+      "find",
+      &["q='a' in parents"],
+      files(&[text_file("f2", "two.txt", "a")]),
+    ),
+  ]);
+
+  let listing = drive.ls("/", vec![FileOpts::Recursive(true)]).unwrap();
+  assert_eq!(names(&listing.files), vec!["A", "one.txt", "two.txt"]);
+  fake.done();
+}
+
+#[test]
+fn test_ls_missing_folder() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'Projects'", "'root' in parents"],
+      files(&[folder("p", "Projects", "r")]),
+    ),
+    on("find", &["name = 'Nope'", "'p' in parents"], files(&[])),
+  ]);
+
+  match drive.ls("/Projects/Nope/Deeper", vec![]) {
+    Err(DriveError::NotFound(path)) => assert_eq!(path, "/Projects/Nope"),
+    other => panic!("Expected NotFound, got {:?}", other),
+  }
+  assert_eq!(fake.count("find"), 2);
+  fake.done();
+}
+
+/// A Drive whose cache is loaded with Projects/Docs under the root folder r
+fn loaded_drive(routes: Vec<Route>) -> (DriveFS, Fake) {
+  loaded_with(
+    &[folder("p", "Projects", "r"), folder("d", "Docs", "p")],
+    routes,
+  )
+}
+
+/// A Drive whose cache is loaded with folders
+fn loaded_with(folders: &[String], mut routes: Vec<Route>) -> (DriveFS, Fake) {
+  let mut all = vec![
+    on(
+      "start_page_token",
+      &[],
+      r#"{"startPageToken": "1"}"#.to_string(),
+    ),
+    on(
+      "find",
+      &["q=mimeType = 'application/vnd.google-apps.folder' and trashed=false"],
+      files(folders),
+    ),
+  ];
+  all.append(&mut routes);
+  let (drive, fake) = Fake::drive(all);
+  (drive.load_cache().unwrap(), fake)
+}
+
+#[test]
+fn test_ls_resolves_path_from_cache() {
+  let (drive, fake) = loaded_drive(vec![on(
+    "find",
+    &["q='d' in parents and trashed=false"],
+    files(&[text_file("f", "notes.txt", "d")]),
+  )]);
+
+  let listing = drive.ls("/Projects/Docs", vec![]).unwrap();
+  assert_eq!(names(&listing.files), vec!["notes.txt"]);
+  // One search to load the folders, and one to list
+  assert_eq!(fake.count("find"), 2);
+  fake.done();
+}
+
+#[test]
+fn test_mkdir_parents() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'a'", "'root' in parents", FOLDER],
+      files(&[folder("a", "a", "r")]),
+    ),
+    on(
+      "find",
+      &["name = 'b'", "'a' in parents", FOLDER],
+      files(&[]),
+    ),
+    on(
+      "find",
+      &["name = 'c'", "'b' in parents", FOLDER],
+      files(&[]),
+    ),
+    on(
+      "create",
+      &[r#""name":"b""#, r#""parents":["a"]"#],
+      folder("b", "b", "a"),
+    ),
+    on(
+      "create",
+      &[r#""name":"c""#, r#""parents":["b"]"#],
+      folder("c", "c", "b"),
+    ),
+    on("find", &["q='c' in parents and trashed=false"], files(&[])),
+  ]);
+
+  let made = drive
+    .mkdir("/a/b/c", vec![FileOpts::Parents(true)])
+    .unwrap();
+  assert_eq!(made.id, Some("c".to_string()));
+  // The new folders went into the cache, so listing them doesn't look the path up again
+  assert!(drive.ls("/a/b/c", vec![]).unwrap().files.is_empty());
+  assert_eq!(fake.count("find"), 4);
+  fake.done();
+}
+
+#[test]
+fn test_mkdir_without_parents() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on("find", &["name = 'a'", "'root' in parents"], files(&[])),
+    on(
+      "find",
+      &["name = 'a'", "'root' in parents"],
+      files(&[folder("a", "a", "r")]),
+    ),
+  ]);
+
+  match drive.mkdir("/a/b", vec![]) {
+    Err(DriveError::Invalid(_)) => (),
+    other => panic!("Expected Invalid, got {:?}", other),
+  }
+  match drive.mkdir("/a", vec![]) {
+    Err(DriveError::AlreadyExists(path)) => assert_eq!(path, "/a"),
+    other => panic!("Expected AlreadyExists, got {:?}", other),
+  }
+  assert_eq!(fake.count("create"), 0);
+  fake.done();
+}
+
+fn trashed_folder(id: &str, name: &str, parent: &str, trashed: bool) -> String {
+  format!(
+    r#"{{"id": "{}", "name": "{}", "parents": ["{}"], "mimeType": "{}", "trashed": {}}}"#,
+    id, name, parent, FOLDER, trashed
+  )
+}
+
+#[test]
+fn test_rm_folder_needs_recursive() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'a'", "'root' in parents"],
+      files(&[folder("a", "a", "r")]),
+    ),
+    on(
+      "find",
+      &["q='a' in parents and trashed=false"],
+      files(&[text_file("x", "x.txt", "a")]),
+    ),
+    on(
+      "find",
+      &["name = 'a'", "'root' in parents"],
+      files(&[folder("a", "a", "r")]),
+    ),
+    on(
+      "update",
+      &["/files/a?", r#"{"trashed":true}"#],
+      trashed_folder("a", "a", "r", true),
+    ),
+  ]);
+
+  let target = || FileRef::Path("/a".to_string());
+  match drive.rm(target(), vec![]) {
+    Err(DriveError::Invalid(_)) => (),
+    other => panic!("Expected Invalid, got {:?}", other),
+  }
+  assert_eq!(fake.count("update"), 0);
+  let trashed = drive.rm(target(), vec![FileOpts::Recursive(true)]).unwrap();
+  assert_eq!(trashed.trashed, Some(true));
+  fake.done();
+}
+
+#[test]
+fn test_rm_and_restore_update_cache() {
+  let (mut drive, fake) = loaded_drive(vec![
+    on(
+      "find",
+      &["name = 'Projects'", "'r' in parents"],
+      files(&[folder("p", "Projects", "r")]),
+    ),
+    on(
+      "update",
+      &["/files/p?", r#"{"trashed":true}"#],
+      trashed_folder("p", "Projects", "r", true),
+    ),
+    on(
+      "find",
+      &["name = 'Projects'", "'r' in parents", FOLDER],
+      files(&[]),
+    ),
+    on(
+      "get",
+      &["/files/p?"],
+      trashed_folder("p", "Projects", "r", true),
+    ),
+    on(
+      "update",
+      &["/files/p?", r#"{"trashed":false}"#],
+      trashed_folder("p", "Projects", "r", false),
+    ),
+    on(
+      "find",
+      &["q='p' in parents and trashed=false"],
+      files(&[folder("d", "Docs", "p")]),
+    ),
+    on("find", &["q='d' in parents and trashed=false"], files(&[])),
+    on(
+      "find",
+      &["q='d' in parents and trashed=false"],
+      files(&[text_file("f", "notes.txt", "d")]),
+    ),
+  ]);
+
+  drive
+    .rm(
+      FileRef::Path("/Projects".to_string()),
+      vec![FileOpts::Recursive(true)],
+    )
+    .unwrap();
+  match drive.ls("/Projects/Docs", vec![]) {
+    Err(DriveError::NotFound(path)) => assert_eq!(path, "/Projects"),
+    other => panic!("Expected NotFound, got {:?}", other),
+  }
+
+  drive.restore(FileRef::Id("p".to_string())).unwrap();
+  let listing = drive.ls("/Projects/Docs", vec![]).unwrap();
+  assert_eq!(names(&listing.files), vec!["notes.txt"]);
+  fake.done();
+}
+
+#[test]
+fn test_delete_permanently() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on("get", &["/files/x?"], text_file("x", "x.txt", "r")),
+    on("delete", &["/files/x?"], String::new()),
+  ]);
+
+  drive
+    .delete_permanently(FileRef::Id("x".to_string()), vec![])
+    .unwrap();
+  fake.done();
+}
+
+#[test]
+fn test_cp_tree() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'src'", "'root' in parents"],
+      files(&[folder("s", "src", "r")]),
+    ),
+    on("find", &["name = 'dst'", "'root' in parents"], files(&[])),
+    on("find", &["name = 'dst'", "'root' in parents"], files(&[])),
+    on(
+      "create",
+      &[r#""name":"dst""#, r#""parents":["root"]"#],
+      folder("d", "dst", "r"),
+    ),
+    on(
+      "find",
+      &["q='s' in parents and trashed=false"],
+      files(&[text_file("f1", "a.txt", "s"), folder("s2", "sub", "s")]),
+    ),
+    on(
+      "copy",
+      &["/files/f1/copy", r#""name":"a.txt""#, r#""parents":["d"]"#],
+      text_file("c1", "a.txt", "d"),
+    ),
+    on(
+      "create",
+      &[r#""name":"sub""#, r#""parents":["d"]"#],
+      folder("d2", "sub", "d"),
+    ),
+    on(
+      "find",
+      &["q='s2' in parents and trashed=false"],
+      files(&[text_file("f2", "b.txt", "s2")]),
+    ),
+    on(
+      "copy",
+      &["/files/f2/copy", r#""name":"b.txt""#, r#""parents":["d2"]"#],
+      text_file("c2", "b.txt", "d2"),
+    ),
+  ]);
+
+  let results = drive.cp("/src", "/dst", true).unwrap();
+  let copied: Vec<(String, String)> = results
+    .iter()
+    .map(|copy| {
+      let id = copy.result.as_ref().unwrap().id.clone().unwrap();
+      (copy.destination.clone(), id)
+    })
+    .collect();
+  assert_eq!(
+    copied,
+    vec![
+      ("/dst".to_string(), "d".to_string()),
+      ("/dst/a.txt".to_string(), "c1".to_string()),
+      ("/dst/sub".to_string(), "d2".to_string()),
+      ("/dst/sub/b.txt".to_string(), "c2".to_string()),
+    ]
+  );
+  fake.done();
+}
+
+#[test]
+fn test_cp_folder_needs_recursive() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'src'", "'root' in parents"],
+      files(&[folder("s", "src", "r")]),
+    ),
+    on("find", &["name = 'dst'", "'root' in parents"], files(&[])),
+    on("find", &["name = 'dst'", "'root' in parents"], files(&[])),
+  ]);
+
+  match drive.cp("/src", "/dst", false) {
+    Err(DriveError::Invalid(_)) => (),
+    other => panic!("Expected Invalid, got {:?}", other),
+  }
+  assert_eq!(fake.count("create"), 0);
+  fake.done();
+}
+
+/// Routes for uploading to /up.bin with a resumable session at SESSION_URI
+fn upload_routes(mut chunks: Vec<Route>) -> Vec<Route> {
+  let mut routes = vec![
+    on(
+      "find",
+      &["name = 'up.bin'", "'root' in parents"],
+      files(&[]),
+    ),
+    on(
+      "upload",
+      &["uploadType=resumable", r#""name":"up.bin""#],
+      String::new(),
+    )
+    .header("Location", SESSION_URI),
+  ];
+  routes.append(&mut chunks);
+  routes
+}
+
+const SESSION_URI: &str = "https://upload.example.com/session";
+
+fn resumable_put(drive: &DriveFS, size: usize) -> Result<File, DriveError> {
+  drive.put(
+    UploadSource::Reader(Box::new(std::io::Cursor::new(vec![7u8; size]))),
+    "/up.bin",
+    vec![
+      UploadOpts::Method(UploadType::Resumable),
+      UploadOpts::ChunkSize(UPLOAD_CHUNK_UNIT),
+    ],
+  )
+}
+
+#[test]
+fn test_resumable_upload_resends_after_failures() {
+  let size = UPLOAD_CHUNK_UNIT + 1000;
+  let (drive, fake) = Fake::drive(upload_routes(vec![
+    fail("upload_chunk", &["Content-Range: bytes 0-262143/*"]),
+    // Asking what arrived fails too, so it is asked again before sending anything
+    fail("upload_chunk", &["Content-Range: bytes */*"]),
+    on("upload_chunk", &["Content-Range: bytes */*"], String::new())
+      .header("Range", "bytes=0-99999"),
+    on(
+      "upload_chunk",
+      &["Content-Range: bytes 100000-262143/*"],
+      String::new(),
+    )
+    .header("Range", "bytes=0-262143"),
+    on(
+      "upload_chunk",
+      &["Content-Range: bytes 262144-263143/263144"],
+      text_file("u", "up.bin", "r"),
+    ),
+  ]));
+
+  let file = resumable_put(&drive, size).unwrap();
+  assert_eq!(file.id, Some("u".to_string()));
+  let calls = fake.calls.borrow();
+  let resent = calls.iter().find(|call| {
+    call.headers.contains(&(
+      "Content-Range".to_string(),
+      "bytes 100000-262143/*".to_string(),
+    ))
+  });
+  assert_eq!(resent.unwrap().body.len(), UPLOAD_CHUNK_UNIT - 100000);
+  fake.done();
+}
+
+#[test]
+fn test_resumable_upload_interrupted() {
+  let size = UPLOAD_CHUNK_UNIT + 1000;
+  let (drive, fake) = Fake::drive(upload_routes(vec![
+    on(
+      "upload_chunk",
+      &["Content-Range: bytes 0-262143/*"],
+      String::new(),
+    )
+    .header("Range", "bytes=0-262143"),
+    fail(
+      "upload_chunk",
+      &["Content-Range: bytes 262144-263143/263144"],
+    ),
+    fail("upload_chunk", &["Content-Range: bytes */263144"]),
+    fail("upload_chunk", &["Content-Range: bytes */263144"]),
+    fail("upload_chunk", &["Content-Range: bytes */263144"]),
+  ]));
+
+  let session = match resumable_put(&drive, size) {
+    Err(DriveError::UploadInterrupted { session, error }) => {
+      assert!(matches!(*error, DriveError::Api { code: 503, .. }));
+      session
+    }
+    other => panic!("Expected UploadInterrupted, got {:?}", other),
+  };
+  assert_eq!(session.uri, SESSION_URI);
+  assert_eq!(session.size, Some(size as u64));
+  fake.done();
+
+  // Resuming skips what Drive already has
+  fake.routes.borrow_mut().extend(vec![
+    on(
+      "upload_chunk",
+      &["Content-Range: bytes */263144"],
+      String::new(),
+    )
+    .header("Range", "bytes=0-262143"),
+    on(
+      "upload_chunk",
+      &["Content-Range: bytes 262144-263143/263144"],
+      text_file("u", "up.bin", "r"),
+    ),
+  ]);
+  let source = UploadSource::Reader(Box::new(std::io::Cursor::new(vec![7u8; size])));
+  let file = drive
+    .resume_upload(
+      &session,
+      source,
+      vec![UploadOpts::ChunkSize(UPLOAD_CHUNK_UNIT)],
+    )
+    .unwrap();
+  assert_eq!(file.id, Some("u".to_string()));
+  fake.done();
+}
+
+#[test]
+fn test_multipart_upload() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'small.txt'", "'root' in parents"],
+      files(&[]),
+    ),
+    on(
+      "upload",
+      &[
+        "uploadType=multipart",
+        "Content-Type: multipart/related",
+        r#""name":"small.txt""#,
+        "Content-Type: text/plain",
+        "aGVsbG8=",
+      ],
+      text_file("s", "small.txt", "r"),
+    ),
+  ]);
+
+  let source = UploadSource::Reader(Box::new(std::io::Cursor::new(b"hello".to_vec())));
+  let file = drive.put(source, "/small.txt", vec![]).unwrap();
+  assert_eq!(file.id, Some("s".to_string()));
+  assert_eq!(fake.count("upload_chunk"), 0);
+  fake.done();
+}
+
+/// An empty directory for the local side of a test
+fn local_dir(name: &str) -> std::path::PathBuf {
+  let dir = std::env::temp_dir().join(format!("drive_fs_{}_{}", name, std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+fn hello_file() -> String {
+  // The md5 of "hello"
+  r#"{"id": "h", "name": "hello.txt", "parents": ["r"], "mimeType": "text/plain", "size": "5", "md5Checksum": "5d41402abc4b2a76b9719d911017c592"}"#.to_string()
+}
+
+#[test]
+fn test_get_to_file() {
+  let dir = local_dir("get_to_file");
+  let local = dir.join("hello.txt");
+  std::fs::write(&local, "keep me").unwrap();
+  let (drive, fake) = Fake::drive(vec![
+    // Missing in Drive
+    on(
+      "find",
+      &["name = 'gone.txt'", "'root' in parents"],
+      files(&[]),
+    ),
+    // Damaged on the way
+    on(
+      "find",
+      &["name = 'hello.txt'", "'root' in parents"],
+      files(&[hello_file()]),
+    ),
+    on("download", &["/files/h?", "alt=media"], "jello".to_string()),
+    // Works
+    on(
+      "find",
+      &["name = 'hello.txt'", "'root' in parents"],
+      files(&[hello_file()]),
+    ),
+    on("download", &["/files/h?", "alt=media"], "hello".to_string()),
+  ]);
+  let part = dir.join("hello.txt.part");
+
+  match drive.get_to_file("/gone.txt", &local) {
+    Err(DriveError::NotFound(_)) => (),
+    other => panic!("Expected NotFound, got {:?}", other),
+  }
+  match drive.get_to_file("/hello.txt", &local) {
+    Err(DriveError::Io(_)) => (),
+    other => panic!("Expected Io, got {:?}", other),
+  }
+  assert_eq!(std::fs::read_to_string(&local).unwrap(), "keep me");
+  assert!(!part.exists());
+
+  drive.get_to_file("/hello.txt", &local).unwrap();
+  assert_eq!(std::fs::read_to_string(&local).unwrap(), "hello");
+  assert!(!part.exists());
+  fake.done();
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn files_page(items: &[String], next_page_token: &str) -> String {
+  format!(
+    r#"{{"kind": "drive#fileList", "incompleteSearch": false, "nextPageToken": "{}", "files": [{}]}}"#,
+    next_page_token,
+    items.join(",")
+  )
+}
+
+#[test]
+fn test_paging() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["q='root' in parents"],
+      files_page(
+        &[text_file("1", "one", "r"), text_file("2", "two", "r")],
+        "p2",
+      ),
+    ),
+    on(
+      "find",
+      &["q='root' in parents", "pageToken=p2"],
+      files_page(
+        // Drive can repeat a file when the folder changes between pages
+        &[text_file("2", "two", "r"), text_file("3", "three", "r")],
+        "p3",
+      ),
+    ),
+    on(
+      "find",
+      &["q='root' in parents", "pageToken=p3"],
+      files(&[text_file("4", "four", "r")]),
+    ),
+  ]);
+
+  let listing = drive.ls("/", vec![]).unwrap();
+  assert_eq!(names(&listing.files), vec!["one", "two", "three", "four"]);
+  assert_eq!(fake.count("find"), 3);
+  fake.done();
+}
+
+#[test]
+fn test_find_iter_fetches_pages_as_needed() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name contains 'report'", "'root' in parents"],
+      files_page(&[text_file("1", "report 1", "r")], "p2"),
+    ),
+    on(
+      "find",
+      &["pageToken=p2"],
+      files(&[text_file("2", "report 2", "r")]),
+    ),
+  ]);
+  let filters = || vec![FileFilter::Name(Filter::Contains("report".to_string()))];
+
+  let first = drive
+    .find_iter("/", filters(), vec![])
+    .unwrap()
+    .next()
+    .unwrap()
+    .unwrap();
+  assert_eq!(first.name, Some("report 1".to_string()));
+  assert_eq!(fake.count("find"), 1);
+  fake.routes.borrow_mut().insert(
+    0,
+    on(
+      "find",
+      &["name contains 'report'", "'root' in parents"],
+      files_page(&[text_file("1", "report 1", "r")], "p2"),
+    ),
+  );
+
+  let found = drive.find("/", filters(), vec![]).unwrap();
+  assert_eq!(names(&found.files), vec!["report 1", "report 2"]);
+  fake.done();
+}
+
+#[test]
+fn test_find_one_without_a_match() {
+  let (drive, fake) = Fake::drive(vec![on(
+    "find",
+    &["name contains 'report'", "'root' in parents"],
+    files(&[]),
+  )]);
+
+  match drive.find_one("/", report_filter(), vec![]) {
+    Err(DriveError::NoMatch(query)) => assert_eq!(query, "name contains 'report' under /"),
+    other => panic!("Expected NoMatch, got {:?}", other),
+  }
+  // Filters Drive has no operator for fail before anything is sent
+  let starts_with = vec![FileFilter::Name(Filter::StartsWith("rep".to_string()))];
+  match drive.find("/", starts_with, vec![]) {
+    Err(DriveError::Unsupported(_)) => (),
+    other => panic!("Expected Unsupported, got {:?}", other),
+  }
+  fake.done();
+}
+
+fn report_filter() -> Vec<FileFilter> {
+  vec![FileFilter::Name(Filter::Contains("report".to_string()))]
+}
+
+#[test]
+fn test_recursive_find() {
+  let (drive, fake) = Fake::drive(vec![
+    // Without a cache the folders are found a level at a time
+    on(
+      "find",
+      &[&format!("q=mimeType = '{}' and 'root' in parents", FOLDER)],
+      files(&[folder("a", "A", "r")]),
+    ),
+    on(
+      "find",
+      &[&format!("q=mimeType = '{}' and 'a' in parents", FOLDER)],
+      files(&[folder("b", "B", "a")]),
+    ),
+    on(
+      "find",
+      &[&format!("q=mimeType = '{}' and 'b' in parents", FOLDER)],
+      files(&[]),
+    ),
+    on(
+      "find",
+      &["q=name contains 'report' and ('root' in parents or 'a' in parents or 'b' in parents)"],
+      files(&[
+        text_file("1", "report 1", "a"),
+        text_file("2", "report 2", "b"),
+      ]),
+    ),
+  ]);
+
+  let found = drive
+    .find("/", report_filter(), vec![FileOpts::Recursive(true)])
+    .unwrap();
+  assert_eq!(names(&found.files), vec!["report 1", "report 2"]);
+  fake.done();
+}
+
+#[test]
+fn test_recursive_find_from_cache() {
+  let (drive, fake) = loaded_drive(vec![on(
+    "find",
+    &["q=name contains 'report' and ('p' in parents or 'd' in parents)"],
+    files(&[text_file("1", "report 1", "d")]),
+  )]);
+
+  let found = drive
+    .find(
+      "/Projects",
+      report_filter(),
+      vec![FileOpts::Recursive(true)],
+    )
+    .unwrap();
+  assert_eq!(names(&found.files), vec!["report 1"]);
+  // The folders came from the cache, so there was only the one search after loading it
+  assert_eq!(fake.count("find"), 2);
+  fake.done();
+}
+
+#[test]
+fn test_duplicate_names() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'Dup'", "'root' in parents"],
+      files(&[folder("x", "Dup", "r"), folder("y", "Dup", "r")]),
+    ),
+    on("get", &["/files/y?"], folder("y", "Dup", "r")),
+    on(
+      "find",
+      &["q='y' in parents and trashed=false"],
+      files(&[text_file("f", "in y.txt", "y")]),
+    ),
+  ]);
+
+  match drive.ls("/Dup", vec![]) {
+    Err(DriveError::Ambiguous { path, ids }) => {
+      assert_eq!(path, "/Dup");
+      assert_eq!(ids, vec!["x", "y"]);
+    }
+    other => panic!("Expected Ambiguous, got {:?}", other),
+  }
+  // An id: segment picks one of them
+  let listing = drive.ls("/id:y", vec![]).unwrap();
+  assert_eq!(names(&listing.files), vec!["in y.txt"]);
+  fake.done();
+}
+
+#[test]
+fn test_paths_in_cache() {
+  let shared = format!(
+    r#"{{"id": "s", "name": "Shared", "parents": ["a", "b"], "mimeType": "{}"}}"#,
+    FOLDER
+  );
+  let (drive, fake) = loaded_with(
+    &[
+      folder("a", "A", "r"),
+      folder("b", "B", "r"),
+      shared,
+      folder("d1", "Dup", "r"),
+      folder("d2", "Dup", "r"),
+    ],
+    vec![
+      on("find", &["q='s' in parents and trashed=false"], files(&[])),
+      on("find", &["q='s' in parents and trashed=false"], files(&[])),
+    ],
+  );
+
+  // A folder with two parents is at both paths
+  drive.ls("/A/Shared", vec![]).unwrap();
+  drive.ls("/B/Shared", vec![]).unwrap();
+  match drive.ls("/Dup", vec![]) {
+    Err(DriveError::Ambiguous { mut ids, .. }) => {
+      ids.sort();
+      assert_eq!(ids, vec!["d1", "d2"]);
+    }
+    other => panic!("Expected Ambiguous, got {:?}", other),
+  }
+  // Nothing but the listings went to Drive
+  assert_eq!(fake.count("find"), 3);
+  fake.done();
+}
+
+#[test]
+fn test_paths_from_drive() {
+  let report =
+    r#"{"id": "f", "name": "report.pdf", "parents": ["a", "b"], "mimeType": "application/pdf"}"#;
+  let (drive, fake) = Fake::drive(vec![
+    on("get", &["/files/root?"], folder("r", "My Drive", "")),
+    on("get", &["/files/f?"], report.to_string()),
+    on("get", &["/files/a?"], folder("a", "A", "r")),
+    on("get", &["/files/b?"], folder("b", "B", "c")),
+    on("get", &["/files/c?"], folder("c", "C", "r")),
+  ]);
+
+  assert_eq!(
+    drive.paths("f").unwrap(),
+    vec!["/A/report.pdf", "/C/B/report.pdf"]
+  );
+  fake.done();
+}
+
+#[test]
+fn test_paths_from_cache() {
+  let (drive, fake) = loaded_drive(vec![on(
+    "get",
+    &["/files/f?"],
+    text_file("f", "notes.txt", "d"),
+  )]);
+
+  assert_eq!(drive.paths("d").unwrap(), vec!["/Projects/Docs"]);
+  assert_eq!(drive.paths("f").unwrap(), vec!["/Projects/Docs/notes.txt"]);
+  fake.done();
+}
+
+#[test]
+fn test_mv_to_shared_drive_root() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'a.txt'", "'root' in parents"],
+      files(&[text_file("f", "a.txt", "r")]),
+    ),
+    on(
+      "drives",
+      &[],
+      r#"{"drives": [{"id": "0AT", "name": "Team"}]}"#.to_string(),
+    ),
+    on("find", &["name = 'a.txt'", "'0AT' in parents"], files(&[])),
+    on(
+      "update",
+      &["/files/f?", "addParents=0AT", "removeParents=r"],
+      text_file("f", "a.txt", "0AT"),
+    ),
+  ]);
+
+  let moved = drive.mv("/a.txt", "//Team/").unwrap();
+  assert_eq!(moved.parents, Some(vec!["0AT".to_string()]));
+  fake.done();
+}
+
+#[test]
+fn test_answer_without_id() {
+  let (mut drive, fake) = Fake::drive(vec![
+    on("get", &["/files/a?"], folder("a", "a", "r")),
+    on(
+      "update",
+      &["/files/a?"],
+      format!(r#"{{"name": "a", "mimeType": "{}"}}"#, FOLDER),
+    ),
+  ]);
+
+  // An error rather than a panic
+  assert!(drive
+    .rm(
+      FileRef::Id("a".to_string()),
+      vec![FileOpts::Recursive(true)]
+    )
+    .is_err());
+  fake.done();
+}
+
+#[test]
+fn test_comments() {
+  let (drive, fake) = Fake::drive(vec![
+    on(
+      "find",
+      &["name = 'plan'", "'root' in parents"],
+      files(&[item("doc", "plan", "r", "application/vnd.google-apps.document")]),
+    ),
+    on(
+      "comments",
+      &["/files/doc/comments?"],
+      r#"{"nextPageToken": "c2", "comments": [{"id": "c1", "content": "Typo here", "resolved": false,
+        "replies": [{"id": "r1", "content": "Fixed"}]}]}"#
+        .to_string(),
+    ),
+    on(
+      "comments",
+      &["/files/doc/comments?", "pageToken=c2"],
+      r#"{"comments": [{"id": "c2", "content": "Nice", "resolved": true}]}"#.to_string(),
+    ),
+    on(
+      "create_comment",
+      &["/files/doc/comments?", r#""content":"Shorter?""#],
+      r#"{"id": "c3", "content": "Shorter?"}"#.to_string(),
+    ),
+    on(
+      "create_reply",
+      &["/files/doc/comments/c1/replies?", r#""action":"resolve""#],
+      r#"{"id": "r2", "content": "", "action": "resolve"}"#.to_string(),
+    ),
+  ]);
+
+  let comments = drive.comments(FileRef::Path("/plan".to_string())).unwrap();
+  let ids: Vec<&str> = comments.iter().map(|comment| comment.id.as_str()).collect();
+  assert_eq!(ids, vec!["c1", "c2"]);
+  assert_eq!(comments[0].replies[0].content, Some("Fixed".to_string()));
+
+  let doc = || FileRef::Id("doc".to_string());
+  let comment = NewComment {
+    content: "Shorter?".to_string(),
+    ..Default::default()
+  };
+  assert_eq!(drive.comment(doc(), comment).unwrap().id, "c3");
+  let reply = drive.resolve_comment(doc(), "c1").unwrap();
+  assert_eq!(reply.action, Some(ReplyAction::Resolve));
+  fake.done();
+}
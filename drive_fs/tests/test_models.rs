@@ -0,0 +1,393 @@
+use api_retry::{Failure, RetryPolicy, Retryable};
+use chrono::{DateTime, Utc};
+use drive_fs::models::{
+  ChangeList, DriveError, Field, FieldMask, File, FileFilter, FileUpdate, Filter, Grantee,
+  MediaResult, MimeType, Permission, Revision, Role, UploadChunkRequest, UploadStatus, Visibility,
+};
+use wrapi::{WrapiError, WrapiResult};
+
+#[test]
+fn test_export_formats() {
+  let sheet_formats = MimeType::Spreadsheet.export_formats();
+  assert!(sheet_formats.contains(&MimeType::Excel));
+  assert!(sheet_formats.contains(&MimeType::CSV));
+  assert!(sheet_formats.contains(&MimeType::PDF));
+  assert!(!sheet_formats.contains(&MimeType::Word));
+
+  let doc_formats = MimeType::Doc.export_formats();
+  assert!(doc_formats.contains(&MimeType::Word));
+  assert!(doc_formats.contains(&MimeType::HTML));
+  assert!(!doc_formats.contains(&MimeType::CSV));
+
+  // Only Google's own types can be exported
+  assert!(MimeType::PDF.export_formats().is_empty());
+  assert!(MimeType::Folder.export_formats().is_empty());
+}
+
+#[test]
+fn test_unlisted_mime_type() {
+  let body = r#"{"id": "abc", "name": "clip.mp4", "mimeType": "video/mp4"}"#;
+  let file = File::parse(vec![], body.as_bytes().to_vec()).unwrap();
+  assert_eq!(
+    file.mime_type,
+    Some(MimeType::Other("video/mp4".to_string()))
+  );
+  assert!(!file.mime_type.unwrap().is_google_type());
+
+  assert_eq!(
+    serde_json::to_string(&MimeType::Other("image/webp".to_string())).unwrap(),
+    r#""image/webp""#
+  );
+  let known: MimeType = serde_json::from_str(r#""application/pdf""#).unwrap();
+  assert_eq!(known, MimeType::PDF);
+}
+
+#[test]
+fn test_shortcut() {
+  let shortcut: MimeType =
+    serde_json::from_str(r#""application/vnd.google-apps.shortcut""#).unwrap();
+  assert_eq!(shortcut, MimeType::Shortcut);
+  assert_eq!(
+    serde_json::to_string(&shortcut).unwrap(),
+    r#""application/vnd.google-apps.shortcut""#
+  );
+
+  let body = r#"{
+    "id": "abc",
+    "name": "Reports",
+    "mimeType": "application/vnd.google-apps.shortcut",
+    "shortcutDetails": {"targetId": "def", "targetMimeType": "application/vnd.google-apps.folder"}
+  }"#;
+  let file = File::parse(vec![], body.as_bytes().to_vec()).unwrap();
+  assert!(file.is_shortcut());
+  let details = file.shortcut_details.unwrap();
+  assert_eq!(details.target_id, "def");
+  assert_eq!(details.target_mime_type, Some(MimeType::Folder));
+}
+
+#[test]
+fn test_filter_escaping() {
+  let filter = FileFilter::Name(Filter::Equals("Bob's \\ Files".to_string()));
+  assert_eq!(filter.to_string().unwrap(), r"name = 'Bob\'s \\ Files'");
+
+  let filter = FileFilter::Type(MimeType::Spreadsheet);
+  assert_eq!(
+    filter.to_string().unwrap(),
+    "mimeType = 'application/vnd.google-apps.spreadsheet'"
+  );
+}
+
+#[test]
+fn test_filter_boolean_tree() {
+  let filter = FileFilter::Name(Filter::Or(Box::new((
+    Filter::Equals("Log".to_string()),
+    Filter::All(vec![
+      Filter::Contains("Sub".to_string()),
+      Filter::Not(Box::new(Filter::Contains("Old".to_string()))),
+    ]),
+  ))));
+  assert_eq!(
+    filter.to_string().unwrap(),
+    "(name = 'Log' or (name contains 'Sub' and not name contains 'Old'))"
+  );
+
+  // A single item doesn't need wrapping, and an empty one can't be searched
+  let single = FileFilter::Name(Filter::Any(vec![Filter::Equals("Log".to_string())]));
+  assert_eq!(single.to_string().unwrap(), "name = 'Log'");
+  assert!(matches!(
+    FileFilter::Name(Filter::Any(vec![])).to_string(),
+    Err(DriveError::Invalid(_))
+  ));
+}
+
+#[test]
+fn test_filter_dates() {
+  let start: DateTime<Utc> = "2020-01-31T08:30:00Z".parse().unwrap();
+  let end: DateTime<Utc> = "2020-02-01T00:00:00Z".parse().unwrap();
+  let filter = FileFilter::ModifiedTime(Filter::And(Box::new((
+    Filter::DateGT(start),
+    Filter::DateLT(end),
+  ))));
+  assert_eq!(
+    filter.to_string().unwrap(),
+    "(modifiedTime > '2020-01-31T08:30:00' and modifiedTime < '2020-02-01T00:00:00')"
+  );
+}
+
+#[test]
+fn test_file_filters() {
+  let parents = FileFilter::Parent(Filter::Any(vec![
+    Filter::Equals("abc".to_string()),
+    Filter::Equals("def".to_string()),
+  ]));
+  assert_eq!(
+    parents.to_string().unwrap(),
+    "('abc' in parents or 'def' in parents)"
+  );
+  assert!(matches!(
+    FileFilter::Parent(Filter::Contains("abc".to_string())).to_string(),
+    Err(DriveError::Unsupported(_))
+  ));
+
+  let text = FileFilter::FullText(Filter::Contains("invoice".to_string()));
+  assert_eq!(text.to_string().unwrap(), "fullText contains 'invoice'");
+  assert!(matches!(
+    FileFilter::FullText(Filter::Equals("invoice".to_string())).to_string(),
+    Err(DriveError::Unsupported(_))
+  ));
+  assert!(matches!(
+    FileFilter::Name(Filter::StartsWith("Sub".to_string())).to_string(),
+    Err(DriveError::Unsupported(_))
+  ));
+
+  let owner = FileFilter::Owner(Filter::Equals("me@example.com".to_string()));
+  assert_eq!(owner.to_string().unwrap(), "'me@example.com' in owners");
+  assert_eq!(
+    FileFilter::IsSharedWithMe(false).to_string().unwrap(),
+    "not sharedWithMe"
+  );
+  assert_eq!(
+    FileFilter::Starred(true).to_string().unwrap(),
+    "starred = true"
+  );
+  assert_eq!(
+    FileFilter::Property("report".to_string(), "weekly".to_string())
+      .to_string()
+      .unwrap(),
+    "properties has { key='report' and value='weekly' }"
+  );
+  assert_eq!(
+    FileFilter::Visibility(Visibility::AnyoneWithLink)
+      .to_string()
+      .unwrap(),
+    "visibility = 'anyoneWithLink'"
+  );
+}
+
+#[test]
+fn test_change_list() {
+  let body = r#"{
+    "newStartPageToken": "1234",
+    "changes": [
+      {"fileId": "abc", "file": {"id": "abc", "name": "Reports", "mimeType": "application/vnd.google-apps.folder", "parents": ["root"]}},
+      {"fileId": "def", "removed": true},
+      {"changeType": "drive", "driveId": "0AB", "removed": false},
+      {"removed": false}
+    ]
+  }"#;
+  let page = ChangeList::parse(vec![], body.as_bytes().to_vec()).unwrap();
+  assert_eq!(page.new_start_page_token, Some("1234".to_string()));
+  assert_eq!(page.next_page_token, None);
+  assert!(!page.changes[0].removed);
+  assert!(page.changes[0].file.as_ref().unwrap().is_folder());
+  assert!(page.changes[1].removed);
+  assert!(page.changes[1].file.is_none());
+  // Changes to shared drives have no file
+  assert_eq!(page.changes[2].change_type, Some("drive".to_string()));
+  assert_eq!(page.changes[2].file_id, None);
+  assert_eq!(page.changes[3].file_id, None);
+}
+
+#[test]
+fn test_permission_body() {
+  let user = Permission::new(
+    Grantee::User("reports@example.com".to_string()),
+    Role::Commenter,
+  );
+  assert_eq!(
+    serde_json::to_string(&user).unwrap(),
+    r#"{"type":"user","role":"commenter","emailAddress":"reports@example.com"}"#
+  );
+  let anyone = Permission::new(Grantee::Anyone, Role::Reader);
+  assert_eq!(
+    serde_json::to_string(&anyone).unwrap(),
+    r#"{"type":"anyone","role":"reader"}"#
+  );
+}
+
+#[test]
+fn test_revision() {
+  let body = r#"{
+    "id": "42",
+    "mimeType": "application/vnd.google-apps.spreadsheet",
+    "modifiedTime": "2020-01-31T08:30:00.000Z",
+    "lastModifyingUser": {"displayName": "Report Bot", "emailAddress": "reports@example.com"},
+    "exportLinks": {"text/csv": "https://docs.google.com/export?format=csv"}
+  }"#;
+  let revision = Revision::parse(vec![], body.as_bytes().to_vec()).unwrap();
+  assert_eq!(revision.mime_type, Some(MimeType::Spreadsheet));
+  assert_eq!(
+    revision.modified_time,
+    Some("2020-01-31T08:30:00Z".parse::<DateTime<Utc>>().unwrap())
+  );
+  assert_eq!(
+    revision.last_modifying_user.unwrap().email_address,
+    Some("reports@example.com".to_string())
+  );
+  assert!(revision.export_links.unwrap().contains_key("text/csv"));
+}
+
+#[test]
+fn test_field_mask() {
+  assert_eq!(
+    FieldMask::standard().to_string(),
+    "id,name,mimeType,parents,trashed,driveId,shortcutDetails,size,md5Checksum"
+  );
+  assert_eq!(
+    FieldMask::new()
+      .with(Field::Starred)
+      .with(Field::Starred)
+      .with(Field::Properties)
+      .to_string(),
+    "id,name,mimeType,parents,trashed,driveId,shortcutDetails,starred,properties"
+  );
+  assert_eq!(FieldMask::default(), FieldMask::new());
+
+  let mut properties = std::collections::HashMap::new();
+  properties.insert("stale".to_string(), None);
+  let update = FileUpdate {
+    properties: Some(properties),
+    ..Default::default()
+  };
+  assert_eq!(
+    serde_json::to_string(&update).unwrap(),
+    r#"{"properties":{"stale":null}}"#
+  );
+}
+
+#[test]
+fn test_drive_error() {
+  let body = |code: u16, reason: &str| {
+    format!(
+      r#"{{"error": {{"code": {}, "message": "Nope", "errors": [{{"domain": "usageLimits", "reason": "{}"}}]}}}}"#,
+      code, reason
+    )
+  };
+  let parse = |text: String| DriveError::from(WrapiError::General(text));
+
+  assert!(matches!(
+    parse(body(404, "notFound")),
+    DriveError::NotFound(_)
+  ));
+  assert!(matches!(
+    parse(body(403, "userRateLimitExceeded")),
+    DriveError::RateLimited(_)
+  ));
+  assert!(matches!(
+    parse(body(403, "storageQuotaExceeded")),
+    DriveError::QuotaExceeded(_)
+  ));
+  assert!(matches!(
+    parse(body(403, "insufficientFilePermissions")),
+    DriveError::PermissionDenied(_)
+  ));
+  match parse(format!("HTTP 500: {}", body(500, "backendError"))) {
+    DriveError::Api { code, reason, .. } => {
+      assert_eq!(code, 500);
+      assert_eq!(reason, "backendError");
+    }
+    other => panic!("Expected an Api error, got {:?}", other),
+  }
+  assert!(matches!(
+    parse("connection reset".to_string()),
+    DriveError::Wrapi(_)
+  ));
+
+  // An error body in place of a file is an error, not a file with nothing set
+  let failed = File::parse(vec![], body(404, "notFound").into_bytes()).unwrap_err();
+  assert!(matches!(DriveError::from(failed), DriveError::NotFound(_)));
+
+  // The same goes for downloads, which only take JSON as content when it isn't an error
+  let json = vec![("Content-Type".to_string(), "application/json".to_string())];
+  let failed = MediaResult::parse(json.clone(), body(404, "notFound").into_bytes()).unwrap_err();
+  assert!(matches!(DriveError::from(failed), DriveError::NotFound(_)));
+  let content = MediaResult::parse(json, br#"{"error": "not a Google error"}"#.to_vec()).unwrap();
+  assert_eq!(
+    content.content,
+    br#"{"error": "not a Google error"}"#.to_vec()
+  );
+  let pdf = vec![("Content-Type".to_string(), "application/pdf".to_string())];
+  let content = MediaResult::parse(pdf, body(404, "notFound").into_bytes()).unwrap();
+  assert!(!content.content.is_empty());
+
+  assert_eq!(
+    DriveError::AlreadyExists("/Reports".to_string()).to_string(),
+    "already exists: /Reports"
+  );
+}
+
+#[test]
+fn test_throttled_download() {
+  let json = (
+    "Content-Type".to_string(),
+    "application/json; charset=UTF-8".to_string(),
+  );
+  let body = r#"{"error": {"code": 429, "message": "Slow down", "errors": [{"reason": "rateLimitExceeded"}]}}"#;
+  let headers = vec![json.clone(), ("Retry-After".to_string(), "3".to_string())];
+  let err = MediaResult::parse(headers, body.as_bytes().to_vec()).unwrap_err();
+  assert_eq!(err.failure(), Failure::Throttled);
+  assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(3)));
+
+  // A throttled chunk is fetched again rather than failing the download
+  let policy = RetryPolicy {
+    base_delay: std::time::Duration::from_millis(0),
+    ..Default::default()
+  };
+  let mut calls = 0;
+  let chunk = policy
+    .run("download", true, || {
+      calls += 1;
+      match calls {
+        1 => MediaResult::parse(vec![json.clone()], body.as_bytes().to_vec()),
+        _ => MediaResult::parse(vec![], b"content".to_vec()),
+      }
+    })
+    .unwrap();
+  assert_eq!(calls, 2);
+  assert_eq!(chunk.content, b"content".to_vec());
+}
+
+#[test]
+fn test_content_range() {
+  let chunk = |content: Vec<u8>, offset: u64, total: Option<u64>| UploadChunkRequest {
+    session_uri: "https://upload.example.com/session".to_string(),
+    content,
+    offset,
+    total,
+  };
+  assert_eq!(chunk(vec![0; 10], 0, None).content_range(), "bytes 0-9/*");
+  assert_eq!(
+    chunk(vec![0; 10], 100, Some(110)).content_range(),
+    "bytes 100-109/110"
+  );
+  // An empty chunk asks for the status
+  assert_eq!(chunk(vec![], 0, None).content_range(), "bytes */*");
+  assert_eq!(chunk(vec![], 0, Some(110)).content_range(), "bytes */110");
+}
+
+#[test]
+fn test_upload_status() {
+  let range = |value: &str| vec![("range".to_string(), value.to_string())];
+  match *UploadStatus::parse(range("bytes=0-262143"), vec![]).unwrap() {
+    UploadStatus::Incomplete(received) => assert_eq!(received, 262144),
+    other => panic!("Expected Incomplete, got {:?}", other),
+  }
+  // Nothing stored yet
+  match *UploadStatus::parse(vec![], vec![]).unwrap() {
+    UploadStatus::Incomplete(received) => assert_eq!(received, 0),
+    other => panic!("Expected Incomplete, got {:?}", other),
+  }
+  assert!(UploadStatus::parse(range("bytes=oops"), vec![]).is_err());
+
+  let body = r#"{"id": "abc", "name": "big.bin", "mimeType": "application/octet-stream"}"#;
+  match *UploadStatus::parse(vec![], body.as_bytes().to_vec()).unwrap() {
+    UploadStatus::Complete(file) => assert_eq!(file.id, Some("abc".to_string())),
+    other => panic!("Expected Complete, got {:?}", other),
+  }
+
+  // The session is gone
+  let body =
+    r#"{"error": {"code": 404, "message": "Not Found", "errors": [{"reason": "notFound"}]}}"#;
+  let err = UploadStatus::parse(vec![], body.as_bytes().to_vec()).unwrap_err();
+  assert!(matches!(DriveError::from(err), DriveError::NotFound(_)));
+}
@@ -5,6 +5,7 @@ use std::cell::RefCell;
 
 use std::collections::HashMap;
 
+pub use api_retry::RetryPolicy;
 use serde_derive::{Deserialize, Serialize};
 use wrapi::{WrapiApi, WrapiError, WrapiRequest, WrapiResult};
 
@@ -346,7 +347,7 @@ pub enum DateTimeRenderOption {
 }
 
 // TOOD: Implement these functions
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StandardParameters {
   fields: Option<String>,
   #[serde(rename = "uploadType")]
@@ -422,7 +423,8 @@ pub struct ValueRange {
 }
 
 impl WrapiResult for ValueRange {
-  fn parse(_headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<ValueRange>, WrapiError> {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<ValueRange>, WrapiError> {
+    api_retry::check_response(&headers, &body)?;
     let contents = std::str::from_utf8(&body)?;
     log::debug!("Value Range contents:\n{:#?}", contents);
     let result = serde_json::from_str(contents)?;
@@ -841,8 +843,9 @@ pub struct Spreadsheet {
 }
 
 impl WrapiResult for Spreadsheet {
-  fn parse(_headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<Spreadsheet>, WrapiError> {
+  fn parse(headers: Vec<(String, String)>, body: Vec<u8>) -> Result<Box<Spreadsheet>, WrapiError> {
     debug!("Parsing the spreadsheet result");
+    api_retry::check_response(&headers, &body)?;
     let str_body = std::str::from_utf8(&body)?;
     let result: Result<Spreadsheet, serde_json::error::Error> = serde_json::from_str(str_body);
     match result {
@@ -862,6 +865,7 @@ pub struct Settings {
   _auto_write: bool,
 }
 
+#[derive(Clone, Debug)]
 pub struct OpenRequest {
   sheet_id: String,
 }
@@ -883,6 +887,7 @@ impl WrapiRequest for OpenRequest {
   }
 }
 
+#[derive(Clone, Debug)]
 pub struct ReadRequest {
   spreadsheet_id: String,
   sheet_name: String,
@@ -929,7 +934,7 @@ impl WrapiRequest for ReadRequest {
   }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct AppendRequest {
   spreadsheet_id: String,
   value_input: ValueInputOption,
@@ -1042,9 +1047,10 @@ pub struct AppendResponse {
 
 impl WrapiResult for AppendResponse {
   fn parse(
-    _headers: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
     body: Vec<u8>,
   ) -> Result<Box<AppendResponse>, WrapiError> {
+    api_retry::check_response(&headers, &body)?;
     let contents = std::str::from_utf8(&body)?;
     log::debug!("AppendResponse:\n{:#?}", contents);
     let result = serde_json::from_str(contents)?;
@@ -1232,9 +1238,10 @@ pub struct MetadataSearchResult {
 
 impl WrapiResult for MetadataSearchResult {
   fn parse(
-    _headers: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
     body: Vec<u8>,
   ) -> Result<Box<MetadataSearchResult>, WrapiError> {
+    api_retry::check_response(&headers, &body)?;
     debug!("Parsing the spreadsheet result");
     let str_body = std::str::from_utf8(&body)?;
     let result: Result<MetadataSearchResult, serde_json::error::Error> =
@@ -1313,11 +1320,11 @@ impl WrapiRequest for BatchUpdateRequest {
 
 impl WrapiResult for BatchUpdateResponse {
   fn parse(
-    _headers: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
     body: Vec<u8>,
   ) -> Result<Box<BatchUpdateResponse>, WrapiError> {
+    api_retry::check_response(&headers, &body)?;
     let contents = std::str::from_utf8(&body)?;
-    println!("Result Body:\n{:#?}", contents);
     let result = serde_json::from_str(contents)?;
     Ok(Box::new(result))
   }
@@ -1325,6 +1332,7 @@ impl WrapiResult for BatchUpdateResponse {
 
 pub struct SheetDB {
   api: RefCell<wrapi::API>,
+  retry: RetryPolicy,
   pub sheet: Box<Spreadsheet>,
   _settings: Settings,
 }
@@ -1337,6 +1345,16 @@ impl SheetDB {
 
   /// Connect to an existing spreadsheet
   pub fn open(auth: wrapi::AuthMethod, sheet_id: String) -> Result<SheetDB, WrapiError> {
+    SheetDB::open_with_retry(auth, sheet_id, RetryPolicy::default())
+  }
+
+  /// Connect to an existing spreadsheet, retrying failed calls with retry instead of the default
+  /// of 5 tries with backoff
+  pub fn open_with_retry(
+    auth: wrapi::AuthMethod,
+    sheet_id: String,
+    retry: RetryPolicy,
+  ) -> Result<SheetDB, WrapiError> {
     log::info!("Opening spreadsheet with ID: {}", sheet_id.clone());
     let api = wrapi::API::new(auth.clone())
       .add_endpoint(
@@ -1386,15 +1404,29 @@ impl SheetDB {
 
     let req = OpenRequest { sheet_id: sheet_id };
     log::debug!("About to query the sheet using the 'open' call");
-    let sheet = api.call("open", req)?;
+    let sheet = retry.run("open", true, || api.call("open", req.clone()))?;
 
     Ok(SheetDB {
       api: RefCell::new(api),
+      retry,
       sheet: sheet,
       _settings: Settings { _auto_write: false },
     })
   }
 
+  /// Make a call through the retry policy. Only batch_update changes the sheet, and it isn't safe to
+  /// repeat since appending twice adds the rows twice
+  fn call<T: WrapiResult, R: WrapiRequest + Clone>(
+    &self,
+    name: &str,
+    req: R,
+  ) -> Result<Box<T>, WrapiError> {
+    let idempotent = name != "batch_update";
+    self.retry.run(name, idempotent, || {
+      self.api.borrow_mut().call(name, req.clone())
+    })
+  }
+
   // list sheets
   pub fn list_sheets(&self) -> Result<Vec<String>, WrapiError> {
     let list = self
@@ -1426,7 +1458,7 @@ impl SheetDB {
             },
           };
 
-          let data: Result<Box<ValueRange>, WrapiError> = self.api.borrow_mut().call("read", req);
+          let data: Result<Box<ValueRange>, WrapiError> = self.call("read", req);
           Some(data)
         }
         false => None,
@@ -1479,14 +1511,14 @@ impl SheetDB {
       },
     };
 
-    self.api.borrow_mut().call("read", req)
+    self.call("read", req)
   }
 
   pub fn search_metadata(
     &self,
     filters: Vec<DataFilter>,
   ) -> Result<Box<MetadataSearchResult>, WrapiError> {
-    self.api.borrow_mut().call(
+    self.call(
       "search",
       DeveloperMetadataSearchRequest {
         sheet_id: self.sheet.spreadsheet_id.clone(),
@@ -1507,12 +1539,12 @@ impl SheetDB {
       response_ranges: vec![],
       response_include_grid_data: false,
     };
-    self.api.borrow_mut().call("batch_update", req)
+    self.call("batch_update", req)
   }
 
   pub fn append_values(&self, values: ValueRange) -> Result<Box<AppendResponse>, WrapiError> {
     let sheet: &Spreadsheet = self.sheet.borrow();
     let req = AppendRequest::new(sheet.spreadsheet_id.clone(), values);
-    self.api.borrow_mut().call("batch_update", req)
+    self.call("batch_update", req)
   }
 }